@@ -1,125 +1,1025 @@
 use std::ffi::c_int;
+use std::net::IpAddr;
+use std::sync::Arc;
 
+use anyhow::{Context, Result};
+use jiff::Timestamp;
 use libc;
 
+use crate::cache;
+use crate::domain::{self, AiFamily};
 use crate::ffi::{pam, types};
 use crate::filter;
-use crate::item;
+use crate::geoip;
+use crate::group;
+use crate::item::{self, RemoteHost};
 use crate::log;
+use crate::neighbour;
 use crate::parser;
-use crate::pattern;
+use crate::policy;
+use crate::rule;
 
-use libc::{LOG_ERR, LOG_INFO};
+use libc::{LOG_ERR, LOG_INFO, LOG_WARNING};
 
-use filter::{Filter, FilterDomain, FilterIp, FilterUser};
-use log::pam_syslog;
+use filter::Filter;
+use log::{Logger, PamLogger, StructuredLogger, pam_syslog};
 use pam::pamh_t;
-use pam::{PAM_AUTH_ERR, PAM_AUTHINFO_UNAVAIL, PAM_SUCCESS};
-use pattern::pat_ipv4;
+use pam::{PAM_AUTH_ERR, PAM_PERM_DENIED};
+use parser::{LogFormat, MissingPolicy, Mode, PassThrough, Precedence, ResolveMatch, ReturnCode};
+use policy::Policy;
+use rule::{Action, Rule, RuleContext};
 use types::argv_t;
 
+// why a login is not admitted
+#[derive(Debug)]
+enum Denial {
+    // rejected by a list, a rule or the policy for a missing value, which
+    // `matched` names, e.g. "ip-deny" or "rule 3"
+    Denied { matched: String, reason: String },
+    // no rule matched and `--on-no-match` replaces the default action
+    Unmatched(String),
+    // a lookup failed; carries the PAM return code
+    Failed(String, c_int),
+}
+
+type Verdict<T = ()> = Result<T, Denial>;
+
+fn denied(matched: &str, reason: String) -> Denial {
+    Denial::Denied {
+        matched: matched.to_owned(),
+        reason,
+    }
+}
+
+fn lookup_failed(policy: &Policy, e: anyhow::Error) -> Denial {
+    Denial::Failed(format!("{:#}", e), policy.on_resolver_error.to_pam())
+}
+
 macro_rules! pam_syslog_on_err {
-    ($e: expr, $pamh: expr $(,)?) => {
+    ($e: expr, $pamh: expr, $code: expr $(,)?) => {
         match $e {
             Ok(x) => x,
             Err(e) => {
                 pam_syslog($pamh, LOG_ERR, &e.to_string());
-                return PAM_AUTHINFO_UNAVAIL;
+                return $code;
             }
         }
     };
 }
 
-fn auth_user(allowed_users: &FilterUser, user: &str, pamh: pamh_t) -> c_int {
+// a deny entry rejects the value unless precedence is given to the allow list
+// and the value is explicitly allowed as well
+fn is_denied(in_deny: bool, in_allow: bool, precedence: Precedence) -> bool {
+    in_deny && !(precedence == Precedence::Allow && in_allow)
+}
+
+fn auth_service(policy: &Policy, service: &str) -> Verdict {
+    let in_allow = policy.service_allow.contains(service);
+
+    if is_denied(
+        policy.service_deny.contains(service),
+        in_allow,
+        policy.precedence,
+    ) {
+        let msg = format!("service '{}' denied by service-deny list", service);
+        return Err(denied("service-deny", msg));
+    }
+
+    // allow all services if rules not set
+    if policy.service_allow.is_empty() || in_allow {
+        return Ok(());
+    }
+
+    let msg = format!("service '{}' not allowed by service-allow list", service);
+    Err(denied("service-allow", msg))
+}
+
+fn needs_groups(policy: &Policy) -> bool {
+    !policy.group_allow.is_empty()
+        || !policy.group_deny.is_empty()
+        || policy.rules.iter().any(Rule::needs_groups)
+}
+
+fn needs_ruser_groups(policy: &Policy, ruser: &str) -> bool {
+    !ruser.is_empty() && policy.rules.iter().any(Rule::needs_ruser_groups)
+}
+
+// group membership is only resolved when a list or rule selects by group
+fn lookup_groups(user: &str, needed: bool) -> Result<Vec<String>> {
+    if !needed {
+        return Ok(Vec::new());
+    }
+
+    group::get_user_groups(user)
+}
+
+// a user is admitted by either its name or any of its groups
+fn auth_user(policy: &Policy, user: &str, groups: &[String], logger: &dyn Logger) -> Verdict {
+    let in_user_deny = policy.user_deny.contains(user);
+    let in_group_deny = policy.group_deny.contains_any(groups);
+    let in_allow = policy.user_allow.contains(user) || policy.group_allow.contains_any(groups);
+
+    if is_denied(in_user_deny || in_group_deny, in_allow, policy.precedence) {
+        let list = if in_user_deny {
+            "user-deny"
+        } else {
+            "group-deny"
+        };
+        let msg = format!("user '{}' denied by {} list", user, list);
+        return Err(denied(list, msg));
+    }
+
     // allow all users if rules not set
-    if allowed_users.is_empty() || allowed_users.contains(user) {
+    if (policy.user_allow.is_empty() && policy.group_allow.is_empty()) || in_allow {
         let msg = format!("user '{}' allowed", user);
-        pam_syslog(pamh, LOG_INFO, &msg);
-        return PAM_SUCCESS;
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
     }
 
-    let msg = format!("user '{}' not allowed", user);
-    pam_syslog(pamh, LOG_ERR, &msg);
-    PAM_AUTH_ERR
+    let list = match (policy.user_allow.is_empty(), policy.group_allow.is_empty()) {
+        (false, false) => "user-allow or group-allow",
+        (false, true) => "user-allow",
+        _ => "group-allow",
+    };
+    let msg = format!("user '{}' not allowed by {} list", user, list);
+    Err(denied(list, msg))
 }
 
+// an IP is only matched against domain lists and rules if its reverse DNS
+// name is forward-confirmed; otherwise no DNS lookup is performed. A failure
+// does not reach --on-resolver-error: most hosts without a PTR record are
+// legitimate and still matched by their IP
+fn verify_rhost(policy: &Policy, rhost: &RemoteHost, logger: &dyn Logger) -> Option<String> {
+    let ip = rhost.ip()?;
+    let has_domains = !policy.domain_allow.is_empty()
+        || !policy.domain_deny.is_empty()
+        || policy.rules.iter().any(Rule::needs_domain);
+
+    if !policy.fcrdns || !has_domains {
+        return None;
+    }
+
+    match domain::get_verified_domain_from_ip(ip) {
+        Ok(x) => {
+            let msg = format!("host '{}' verified as '{}'", rhost, x);
+            logger.log(LOG_INFO, &msg);
+            Some(x)
+        }
+        Err(e) => {
+            let msg = format!(
+                "host '{}' not verified, domain rules skipped: {:#}",
+                rhost, e
+            );
+            logger.log(LOG_ERR, &msg);
+            None
+        }
+    }
+}
+
+// with --resolve-rhost, a hostname is matched against the IP lists by the
+// addresses it resolves to
+fn resolve_rhost(policy: &Policy, rhost: &RemoteHost, logger: &dyn Logger) -> Verdict<Vec<IpAddr>> {
+    let RemoteHost::Name(name) = rhost else {
+        return Ok(Vec::new());
+    };
+
+    if !policy.resolve_rhost || (policy.ip_allow.is_empty() && policy.ip_deny.is_empty()) {
+        return Ok(Vec::new());
+    }
+
+    let lookup = domain::get_ip_from_domain(name, AiFamily::AF_UNSPEC)
+        .with_context(|| format!("forward lookup of '{}' failed", name))
+        .map_err(|e| lookup_failed(policy, e))?;
+
+    // getaddrinfo returns an address once for each socket type
+    let mut addrs: Vec<_> = lookup.iter().map(IpAddr::to_canonical).collect();
+    addrs.sort();
+    addrs.dedup();
+
+    let list: Vec<_> = addrs.iter().map(IpAddr::to_string).collect();
+    let msg = format!("host '{}' resolved to '{}'", name, list.join(","));
+    logger.log(LOG_INFO, &msg);
+
+    Ok(addrs)
+}
+
+fn auth_ruser(policy: &Policy, ruser: &str, logger: &dyn Logger) -> Verdict {
+    // allow all requesting users if rules not set
+    if policy.ruser_allow.is_empty() && policy.ruser_deny.is_empty() {
+        return Ok(());
+    }
+
+    if ruser.is_empty() {
+        return auth_missing(
+            policy.ruser_missing,
+            "ruser-missing",
+            "remote user not set",
+            logger,
+        );
+    }
+
+    let in_allow = policy.ruser_allow.contains(ruser);
+
+    if is_denied(
+        policy.ruser_deny.contains(ruser),
+        in_allow,
+        policy.precedence,
+    ) {
+        let msg = format!("remote user '{}' denied by ruser-deny list", ruser);
+        return Err(denied("ruser-deny", msg));
+    }
+
+    if policy.ruser_allow.is_empty() || in_allow {
+        let msg = format!("remote user '{}' allowed", ruser);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
+    }
+
+    let msg = format!("remote user '{}' not allowed by ruser-allow list", ruser);
+    Err(denied("ruser-allow", msg))
+}
+
+// `resolved` holds the addresses of a hostname given --resolve-rhost
 fn auth_rhost(
-    allowed_ips: &FilterIp,
-    allowed_domains: &FilterDomain,
-    rhost: &str,
-    pamh: pamh_t,
-) -> c_int {
-    let msg_allow = format!("host '{}' allowed", rhost);
-    let msg_deny = format!("host '{}' not allowed", rhost);
-
-    let is_ip_set = !allowed_ips.is_empty();
-    let is_domain_set = !allowed_domains.is_empty();
-
-    if !is_ip_set && !is_domain_set {
-        pam_syslog(pamh, LOG_INFO, &msg_allow);
-        return PAM_SUCCESS;
-    }
-
-    // not sure why fancy_regex returns Result while std doesn't
-    match pat_ipv4().is_match(rhost).unwrap_or(false) {
-        // I wish Rust has goto
-        true => {
-            if !is_ip_set {
-                // if IP is provided but only domain rules set
-                if is_domain_set {
-                    pam_syslog(pamh, LOG_ERR, &msg_deny);
-                    return PAM_AUTH_ERR;
-                }
-            } else if !allowed_ips.contains(rhost) {
-                pam_syslog(pamh, LOG_ERR, &msg_deny);
-                return PAM_AUTH_ERR;
-            }
+    policy: &Policy,
+    rhost: &RemoteHost,
+    verified: Option<&str>,
+    resolved: &[IpAddr],
+    logger: &dyn Logger,
+) -> Verdict {
+    let (ips, domain) = match rhost {
+        RemoteHost::Ip(ip) => (std::slice::from_ref(ip), verified),
+        RemoteHost::Name(name) => (resolved, Some(name.as_str())),
+        RemoteHost::Local => (&[][..], None),
+    };
+
+    let in_ip_allow = match policy.resolve_match {
+        _ if ips.is_empty() => false,
+        ResolveMatch::Any => ips.iter().any(|x| policy.ip_allow.contains(x)),
+        ResolveMatch::All => ips.iter().all(|x| policy.ip_allow.contains(x)),
+    };
+    let in_ip_deny = ips.iter().any(|x| policy.ip_deny.contains(x));
+    let in_domain_allow = domain.is_some_and(|x| policy.domain_allow.contains(x));
+    let in_domain_deny = domain.is_some_and(|x| policy.domain_deny.contains(x));
+    let in_allow = in_ip_allow || in_domain_allow;
+
+    if is_denied(in_ip_deny || in_domain_deny, in_allow, policy.precedence) {
+        let list = if in_ip_deny { "ip-deny" } else { "domain-deny" };
+        let msg = format!("host '{}' denied by {} list", rhost, list);
+        return Err(denied(list, msg));
+    }
+
+    // allow all hosts if rules not set
+    if (policy.ip_allow.is_empty() && policy.domain_allow.is_empty()) || in_allow {
+        let msg = format!("host '{}' allowed", rhost);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
+    }
+
+    // name the allow lists that are set, and why the host could not be
+    // matched against one of them
+    let list = match (policy.ip_allow.is_empty(), policy.domain_allow.is_empty()) {
+        (false, false) => "ip-allow or domain-allow",
+        (false, true) => "ip-allow",
+        _ => "domain-allow",
+    };
+    let note = match (ips.is_empty(), domain) {
+        (true, _) if policy.domain_allow.is_empty() => " (host is not an IP)",
+        (false, None) if policy.ip_allow.is_empty() => " (host has no verified name)",
+        _ => "",
+    };
+    let msg = format!("host '{}' not allowed by {} list{}", rhost, list, note);
+    Err(denied(list, msg))
+}
+
+fn auth_port(policy: &Policy, port: Option<u16>, logger: &dyn Logger) -> Verdict {
+    // allow all ports if rules not set
+    if policy.port_allow.is_empty() {
+        return Ok(());
+    }
+
+    // fail closed when the listening port cannot be determined, e.g. the
+    // service is not sshd or SSH_CONNECTION is not exported to PAM
+    let Some(port) = port else {
+        let msg = "listening port unknown, denied by port-allow list".to_owned();
+        return Err(denied("port-allow", msg));
+    };
+
+    if policy.port_allow.contains(&port) {
+        let msg = format!("port '{}' allowed", port);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
+    }
+
+    let msg = format!("port '{}' not allowed by port-allow list", port);
+    Err(denied("port-allow", msg))
+}
+
+fn auth_country(policy: &Policy, rhost: &RemoteHost, logger: &dyn Logger) -> Verdict {
+    // the database is only opened if country rules are set
+    let Some(db) = &policy.country_db else {
+        return Ok(());
+    };
+
+    let country = match rhost.ip() {
+        Some(ip) => geoip::lookup_country(db, ip).map_err(|e| lookup_failed(policy, e))?,
+        None => None,
+    };
+
+    let Some(country) = country else {
+        // an unknown country is never in a deny list
+        if policy.country_allow.is_empty() {
+            return Ok(());
         }
-        false => {
-            if !is_domain_set {
-                // if domain is provided but only IP rules set
-                // do not perform reverse DNS lookup and deny immediately
-                if is_ip_set {
-                    pam_syslog(pamh, LOG_ERR, &msg_deny);
-                    return PAM_AUTH_ERR;
-                }
-            } else if !allowed_domains.contains(rhost) {
-                pam_syslog(pamh, LOG_ERR, &msg_deny);
-                return PAM_AUTH_ERR;
-            }
+
+        let msg = format!("host '{}' has no country entry", rhost);
+        return auth_missing(policy.geoip_missing, "geoip-missing", &msg, logger);
+    };
+
+    let in_allow = policy.country_allow.contains(&country);
+
+    if is_denied(
+        policy.country_deny.contains(&country),
+        in_allow,
+        policy.precedence,
+    ) {
+        let msg = format!(
+            "host '{}' in country '{}' denied by country-deny list",
+            rhost, country
+        );
+        return Err(denied("country-deny", msg));
+    }
+
+    if policy.country_allow.is_empty() || in_allow {
+        let msg = format!("host '{}' in country '{}' allowed", rhost, country);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
+    }
+
+    let msg = format!(
+        "host '{}' in country '{}' not allowed by country-allow list",
+        rhost, country
+    );
+    Err(denied("country-allow", msg))
+}
+
+fn auth_asn(policy: &Policy, rhost: &RemoteHost, logger: &dyn Logger) -> Verdict {
+    // the database is only opened if ASN rules are set
+    let Some(db) = &policy.asn_db else {
+        return Ok(());
+    };
+
+    let asn = match rhost.ip() {
+        Some(ip) => geoip::lookup_asn(db, ip).map_err(|e| lookup_failed(policy, e))?,
+        None => None,
+    };
+
+    let Some(asn) = asn else {
+        // an unknown ASN is never in a deny list
+        if policy.asn_allow.is_empty() {
+            return Ok(());
         }
+
+        let msg = format!("host '{}' has no ASN entry", rhost);
+        return auth_missing(policy.geoip_missing, "geoip-missing", &msg, logger);
+    };
+
+    let in_allow = policy.asn_allow.contains(&asn);
+
+    if is_denied(policy.asn_deny.contains(&asn), in_allow, policy.precedence) {
+        let msg = format!("host '{}' in AS{} denied by asn-deny list", rhost, asn);
+        return Err(denied("asn-deny", msg));
+    }
+
+    if policy.asn_allow.is_empty() || in_allow {
+        let msg = format!("host '{}' in AS{} allowed", rhost, asn);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
     }
 
-    pam_syslog(pamh, LOG_INFO, &msg_allow);
-    PAM_SUCCESS
+    let msg = format!(
+        "host '{}' in AS{} not allowed by asn-allow list",
+        rhost, asn
+    );
+    Err(denied("asn-allow", msg))
 }
 
-pub fn authenticate(pamh: pamh_t, _flags: c_int, argc: c_int, argv: argv_t) -> c_int {
-    let parsed = pam_syslog_on_err!(parser::process_pam_args(argc, argv), pamh);
-    let conn = pam_syslog_on_err!(item::get_pam_connection(pamh), pamh);
+fn auth_tty(policy: &Policy, tty: &str, logger: &dyn Logger) -> Verdict {
+    // allow all terminals if rules not set
+    if policy.tty_allow.is_empty() {
+        return Ok(());
+    }
+
+    if tty.is_empty() {
+        return auth_missing(
+            policy.tty_missing,
+            "tty-missing",
+            "session has no tty",
+            logger,
+        );
+    }
+
+    if policy.tty_allow.contains(tty) {
+        let msg = format!("tty '{}' allowed", tty);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
+    }
+
+    let msg = format!("tty '{}' not allowed by tty-allow list", tty);
+    Err(denied("tty-allow", msg))
+}
+
+fn auth_time(policy: &Policy, now: Timestamp, logger: &dyn Logger) -> Verdict {
+    // allow any time if rules not set
+    if policy.time_allow.is_empty() {
+        return Ok(());
+    }
+
+    if policy.time_allow.contains(&now) {
+        let msg = format!("time '{}' allowed", now);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
+    }
+
+    let msg = format!("time '{}' not allowed by time-allow list", now);
+    Err(denied("time-allow", msg))
+}
+
+fn auth_missing(policy: MissingPolicy, option: &str, msg: &str, logger: &dyn Logger) -> Verdict {
+    match policy {
+        MissingPolicy::Allow => {
+            logger.log(LOG_INFO, &format!("{}, allowed by policy", msg));
+            Ok(())
+        }
+        MissingPolicy::Deny => Err(denied(option, format!("{}, denied by policy", msg))),
+    }
+}
+
+fn auth_mac(policy: &Policy, rhost: &RemoteHost, logger: &dyn Logger) -> Verdict {
+    // allow all hardware addresses if rules not set
+    if policy.mac_allow.is_empty() {
+        return Ok(());
+    }
+
+    let Some(ip) = rhost.ip() else {
+        let msg = format!("host '{}' is not an IP, no neighbour entry", rhost);
+        return auth_missing(policy.mac_missing, "mac-missing", &msg, logger);
+    };
+
+    let neigh_table = policy.neigh_table.as_deref();
+
+    let Some(mac) = neighbour::lookup_mac(ip, neigh_table).map_err(|e| lookup_failed(policy, e))?
+    else {
+        let msg = format!("host '{}' has no neighbour entry", rhost);
+        return auth_missing(policy.mac_missing, "mac-missing", &msg, logger);
+    };
+
+    let mac_str = neighbour::format_mac(&mac);
+
+    if policy.mac_allow.contains(&mac) {
+        let msg = format!("host '{}' with MAC '{}' allowed", rhost, mac_str);
+        logger.log(LOG_INFO, &msg);
+        return Ok(());
+    }
+
+    let msg = format!(
+        "host '{}' with MAC '{}' not allowed by mac-allow list",
+        rhost, mac_str
+    );
+    Err(denied("mac-allow", msg))
+}
+
+// the permitting rule, if any, is named in the decision log
+fn auth_rules(policy: &Policy, ctx: &RuleContext, logger: &dyn Logger) -> Verdict<Option<String>> {
+    // rules are optional; without any, the default action does not apply
+    if policy.rules.is_empty() {
+        return Ok(None);
+    }
+
+    let (action, matched) = match rule::find_first_match(&policy.rules, ctx) {
+        Some((index, rule)) => (rule.action, format!("rule {}", index)),
+        None if policy.on_no_match.is_some() => {
+            let msg = format!("no rule matched user '{}'", ctx.user);
+            return Err(Denial::Unmatched(msg));
+        }
+        None => (policy.default_action, "default-action".to_owned()),
+    };
 
-    let filter_user_allow = pam_syslog_on_err!(filter::filter_from_users(parsed.user_allow), pamh);
-    let filter_ipv4_allow = pam_syslog_on_err!(filter::filter_from_ips(parsed.ip_allow), pamh);
-    let filter_domain_allow =
-        pam_syslog_on_err!(filter::filter_from_domains(parsed.domain_allow), pamh);
+    match action {
+        Action::Permit => {
+            let msg = format!("user '{}' permitted by {}", ctx.user, matched);
+            logger.log(LOG_INFO, &msg);
+            Ok(Some(matched))
+        }
+        Action::Deny => {
+            let msg = format!("user '{}' denied by {}", ctx.user, matched);
+            Err(denied(&matched, msg))
+        }
+    }
+}
 
-    #[allow(unused_variables)]
+// every check in the order of evaluation; the first denial stops it
+fn check(
+    policy: &Policy,
+    conn: &item::Connection,
+    now: Timestamp,
+    logger: &dyn Logger,
+) -> Verdict<Option<String>> {
     let item::Connection {
         user,
         service,
         ruser,
-        rhost,
-    } = &conn;
+        remote,
+        tty,
+        server_port,
+        ..
+    } = conn;
+
+    auth_service(policy, service)?;
+
+    let groups = lookup_groups(user, needs_groups(policy));
+    let ruser_groups = lookup_groups(ruser, needs_ruser_groups(policy, ruser));
+
+    let (groups, ruser_groups) = match (groups, ruser_groups) {
+        (Ok(x), Ok(y)) => (x, y),
+        (Err(e), _) | (_, Err(e)) => {
+            let msg = format!("group lookup failed: {:#}", e);
+            return Err(Denial::Failed(msg, policy.group_error.to_pam()));
+        }
+    };
+
+    auth_user(policy, user, &groups, logger)?;
+    auth_ruser(policy, ruser, logger)?;
+
+    let verified = verify_rhost(policy, remote, logger);
+    let resolved = resolve_rhost(policy, remote, logger)?;
+
+    auth_rhost(policy, remote, verified.as_deref(), &resolved, logger)?;
+    auth_country(policy, remote, logger)?;
+    auth_asn(policy, remote, logger)?;
+    auth_port(policy, *server_port, logger)?;
+    auth_tty(policy, tty, logger)?;
+    auth_time(policy, now, logger)?;
+    auth_mac(policy, remote, logger)?;
+
+    let ctx = RuleContext {
+        user,
+        groups: &groups,
+        ruser,
+        ruser_groups: &ruser_groups,
+        rhost: remote,
+        domain: verified.as_deref(),
+        service,
+        tty,
+    };
+
+    auth_rules(policy, &ctx, logger)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Allow,
+    Deny,
+    // rules are set but none matched and `--on-no-match` is given
+    NoMatch,
+    Error,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Allow => "allow",
+            Outcome::Deny => "deny",
+            Outcome::NoMatch => "no-match",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// Outcome of one evaluation before audit mode is applied.
+#[derive(Debug)]
+pub struct Decision {
+    pub decision: Outcome,
+    /// list, rule or missing policy that decided, empty if none did
+    pub matched: String,
+    pub reason: String,
+    pub code: c_int,
+}
+
+/// Runs every check against `conn` at `now`. The same code backs the module
+/// and `pam-network-filter-check`; `deny_code` is returned on a denial
+/// unless `--on-deny` is given.
+pub fn decide(
+    policy: &Policy,
+    conn: &item::Connection,
+    now: Timestamp,
+    logger: &dyn Logger,
+    deny_code: c_int,
+) -> Decision {
+    let logger = StructuredLogger {
+        inner: logger,
+        format: policy.log_format,
+    };
+
+    let (decision, matched, reason, code) = match check(policy, conn, now, &logger) {
+        Ok(Some(x)) => {
+            let reason = format!("permitted by {}", x);
+            (Outcome::Allow, x, reason, policy.on_allow.to_pam())
+        }
+        Ok(None) => {
+            let reason = "all checks passed".to_owned();
+            (
+                Outcome::Allow,
+                String::new(),
+                reason,
+                policy.on_allow.to_pam(),
+            )
+        }
+        Err(Denial::Denied { matched, reason }) => {
+            let code = policy.on_deny.map_or(deny_code, ReturnCode::to_pam);
+            (Outcome::Deny, matched, reason, code)
+        }
+        Err(Denial::Unmatched(reason)) => {
+            let code = policy.on_no_match.map_or(deny_code, ReturnCode::to_pam);
+            (Outcome::NoMatch, String::new(), reason, code)
+        }
+        Err(Denial::Failed(reason, code)) => (Outcome::Error, String::new(), reason, code),
+    };
+
+    Decision {
+        decision,
+        matched,
+        reason,
+        code,
+    }
+}
+
+// the verbs of the text line in audit mode
+fn audit_verb(decision: Outcome) -> &'static str {
+    match decision {
+        Outcome::Allow => "would allow",
+        Outcome::Deny => "would deny",
+        Outcome::NoMatch => "no match for",
+        Outcome::Error => "would fail",
+    }
+}
+
+// an error before any check ran and how it is reported
+#[derive(Debug)]
+struct SetupError {
+    msg: String,
+    priority: c_int,
+    code: c_int,
+}
+
+impl SetupError {
+    // in audit mode, a broken policy must not lock anybody out either
+    fn new(e: anyhow::Error, code: c_int, audit: Option<PassThrough>) -> Self {
+        match audit {
+            Some(x) => SetupError {
+                msg: format!("audit mode, error ignored: {}", e),
+                priority: LOG_WARNING,
+                code: x.to_pam(),
+            },
+            None => SetupError {
+                msg: e.to_string(),
+                priority: LOG_ERR,
+                code,
+            },
+        }
+    }
+}
+
+fn audit_return(mode: Mode, audit_return: PassThrough) -> Option<PassThrough> {
+    (mode == Mode::Audit).then_some(audit_return)
+}
+
+// long-lived applications call the module many times, so the policy is only
+// built again when the arguments or one of its files change
+fn load_policy(args: Vec<String>, config_error: c_int) -> Result<Arc<Policy>, SetupError> {
+    if let Some(x) = cache::get(&args) {
+        return Ok(x);
+    }
+
+    let mut files = cache::snapshot(parser::config_path(&args));
+    let parsed =
+        parser::process_args(args.clone()).map_err(|e| SetupError::new(e, config_error, None))?;
+
+    files.extend(cache::snapshot(parsed.files()));
+
+    let audit = audit_return(parsed.mode, parsed.audit_return);
+    let config_error = parsed.on_config_error.to_pam();
+    let policy =
+        policy::policy_from_cli(parsed).map_err(|e| SetupError::new(e, config_error, audit))?;
+
+    Ok(cache::insert(args, files, policy))
+}
+
+// the PAM items could not be read
+fn connection_failed(policy: &Policy, e: anyhow::Error) -> SetupError {
+    let audit = audit_return(policy.mode, policy.audit_return);
+
+    SetupError::new(e, policy.on_resolver_error.to_pam(), audit)
+}
+
+// shared by the auth and account phases, `phase` only names it in the log
+fn evaluate(pamh: pamh_t, argc: c_int, argv: argv_t, phase: &str, deny_code: c_int) -> c_int {
+    // taken from the module arguments alone, as parsing the rest may fail
+    let config_error = parser::config_error_code(argc, argv).to_pam();
+
+    let args = pam_syslog_on_err!(parser::pam_args(argc, argv), pamh, config_error);
+
+    let policy = match load_policy(args, config_error) {
+        Ok(x) => x,
+        Err(e) => {
+            pam_syslog(pamh, e.priority, &e.msg);
+            return e.code;
+        }
+    };
+
+    let conn = match item::get_pam_connection(pamh) {
+        Ok(x) => x,
+        Err(e) => {
+            let e = connection_failed(&policy, e);
+            pam_syslog(pamh, e.priority, &e.msg);
+            return e.code;
+        }
+    };
+
+    let logger = PamLogger(pamh);
+    let decision = decide(&policy, &conn, Timestamp::now(), &logger, deny_code);
+    let msg = decision_message(&policy, &conn, phase, &decision);
+
+    let mut priority = match decision.decision {
+        Outcome::Allow | Outcome::NoMatch => LOG_INFO,
+        Outcome::Deny | Outcome::Error => LOG_ERR,
+    };
+    let mut code = decision.code;
 
-    if auth_user(&filter_user_allow, user, pamh) != PAM_SUCCESS {
-        return PAM_AUTH_ERR;
+    // in audit mode a denial is only reported; the login goes on as if the
+    // module were not in the stack
+    if policy.mode == Mode::Audit {
+        priority = LOG_WARNING;
+        code = policy.audit_return.to_pam();
     }
 
-    if auth_rhost(&filter_ipv4_allow, &filter_domain_allow, rhost, pamh) != PAM_SUCCESS {
-        return PAM_AUTH_ERR;
+    logger.log(priority, &msg);
+    code
+}
+
+// the line logged for `decision` in the format of `--log-format`
+fn decision_message(
+    policy: &Policy,
+    conn: &item::Connection,
+    phase: &str,
+    decision: &Decision,
+) -> String {
+    let Decision {
+        decision,
+        matched,
+        reason,
+        ..
+    } = decision;
+    let audit = policy.mode == Mode::Audit;
+
+    match policy.log_format {
+        LogFormat::Text if audit => format!(
+            "{} user '{}' from '{}' in {} ({})",
+            audit_verb(*decision),
+            conn.user,
+            conn.rhost,
+            phase,
+            reason
+        ),
+        LogFormat::Text if *decision == Outcome::Allow => {
+            format!("'{}@{}' {} succeeded", conn.user, conn.rhost, phase)
+        }
+        // the reason names the normalized host, so a raw PAM_RHOST that
+        // differs from it is added
+        LogFormat::Text if conn.rhost != conn.remote.to_string() => {
+            format!("{} (rhost '{}')", reason, conn.rhost)
+        }
+        LogFormat::Text => reason.clone(),
+        format => {
+            let decision = if audit {
+                format!("would-{}", decision.as_str())
+            } else {
+                decision.as_str().to_owned()
+            };
+
+            let fields = [
+                ("decision", decision.as_str()),
+                ("reason", reason.as_str()),
+                ("matched", matched.as_str()),
+                ("user", conn.user.as_str()),
+                ("ruser", conn.ruser.as_str()),
+                ("rhost", conn.rhost.as_str()),
+                ("service", conn.service.as_str()),
+                ("tty", conn.tty.as_str()),
+                ("phase", phase),
+            ];
+
+            match format {
+                LogFormat::Json => log::format_json(&fields),
+                _ => log::format_kv(&fields),
+            }
+        }
     }
+}
+
+pub fn authenticate(pamh: pamh_t, _flags: c_int, argc: c_int, argv: argv_t) -> c_int {
+    evaluate(pamh, argc, argv, "authentication", PAM_AUTH_ERR)
+}
+
+// the auth stack is skipped for public key, GSSAPI and certificate logins in
+// sshd, so the same policy is enforced again in the account stack
+pub fn acct_mgmt(pamh: pamh_t, _flags: c_int, argc: c_int, argv: argv_t) -> c_int {
+    // PAM_PERM_DENIED is the conventional denial of the account phase
+    evaluate(pamh, argc, argv, "account", PAM_PERM_DENIED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    struct NullLogger;
+
+    impl Logger for NullLogger {
+        fn log(&self, _priority: c_int, _msg: &str) {}
+    }
+
+    fn policy(argv: &[&str]) -> Result<Policy> {
+        policy::policy_from_cli(parser::process_args(args(argv))?)
+    }
+
+    fn rhost_denial(policy: &Policy, rhost: &str) -> (String, String) {
+        match auth_rhost(policy, &RemoteHost::parse(rhost), None, &[], &NullLogger) {
+            Err(Denial::Denied { matched, reason }) => (matched, reason),
+            x => panic!("unexpected verdict {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_auth_rhost_tn_list_names() -> Result<()> {
+        let ip_only = policy(&["lib", "--ip-allow=10.0.0.0/8"])?;
+        let domain_only = policy(&["lib", "--domain-allow=.corp.example"])?;
+        let both = policy(&[
+            "lib",
+            "--ip-allow=10.0.0.0/8",
+            "--domain-allow=.corp.example",
+        ])?;
+
+        assert_eq!(
+            rhost_denial(&ip_only, "host.example"),
+            (
+                "ip-allow".to_owned(),
+                "host 'host.example' not allowed by ip-allow list (host is not an IP)".to_owned()
+            )
+        );
+        assert_eq!(
+            rhost_denial(&ip_only, "192.0.2.1").1,
+            "host '192.0.2.1' not allowed by ip-allow list"
+        );
+        assert_eq!(
+            rhost_denial(&domain_only, "192.0.2.1"),
+            (
+                "domain-allow".to_owned(),
+                "host '192.0.2.1' not allowed by domain-allow list (host has no verified name)"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            rhost_denial(&both, "host.example").1,
+            "host 'host.example' not allowed by ip-allow or domain-allow list"
+        );
+
+        Ok(())
+    }
+
+    fn connection(user: &str, rhost: &str) -> item::Connection {
+        item::Connection {
+            service: "sshd".to_owned(),
+            user: user.to_owned(),
+            ruser: String::new(),
+            rhost: rhost.to_owned(),
+            remote: RemoteHost::parse(rhost),
+            tty: String::new(),
+            client_port: None,
+            server_port: None,
+        }
+    }
+
+    fn message(policy: &Policy, conn: &item::Connection) -> String {
+        let decision = decide(policy, conn, Timestamp::now(), &NullLogger, PAM_AUTH_ERR);
 
-    let msg = format!("'{}@{}' authentication succeeded", user, rhost);
-    pam_syslog(pamh, LOG_INFO, &msg);
-    PAM_SUCCESS
+        decision_message(policy, conn, "authentication", &decision)
+    }
+
+    #[test]
+    fn test_decision_message_tp_raw_rhost() -> Result<()> {
+        let policy = policy(&["lib", "--ip-allow=10.0.0.0/8"])?;
+
+        assert_eq!(
+            message(&policy, &connection("root", "::ffff:192.0.2.1")),
+            "host '192.0.2.1' not allowed by ip-allow list (rhost '::ffff:192.0.2.1')"
+        );
+        assert_eq!(
+            message(&policy, &connection("root", "host.example.:22")),
+            "host 'host.example' not allowed by ip-allow list (host is not an IP) \
+             (rhost 'host.example.:22')"
+        );
+        assert_eq!(
+            message(&policy, &connection("root", "192.0.2.1")),
+            "host '192.0.2.1' not allowed by ip-allow list"
+        );
+
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct RecordingLogger(std::cell::RefCell<Vec<String>>);
+
+    impl Logger for RecordingLogger {
+        fn log(&self, _priority: c_int, msg: &str) {
+            self.0.borrow_mut().push(msg.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_decide_tn_forged_check_fields() -> Result<()> {
+        let conn = connection("a' decision=\"allow", "x\" y=\"z");
+
+        for (format, prefix) in [("kv", "msg=\""), ("json", "{\"msg\":\"")] {
+            let policy = policy(&["lib", "--rule=permit", &format!("--log-format={}", format)])?;
+            let logger = RecordingLogger::default();
+
+            decide(&policy, &conn, Timestamp::now(), &logger, PAM_AUTH_ERR);
+
+            let lines = logger.0.into_inner();
+            assert!(!lines.is_empty());
+
+            for line in lines {
+                assert!(line.starts_with(prefix), "{}", line);
+                assert!(!line.contains("decision=\"") && !line.contains("y=\""));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_policy_tn_invalid_entry() -> Result<()> {
+        let argv = args(&["lib", "--ip-allow=10.0.0.0/33"]);
+        let ret = load_policy(argv, pam::PAM_AUTHINFO_UNAVAIL).expect_err("must fail");
+
+        assert_eq!(ret.priority, LOG_ERR);
+        assert_eq!(ret.code, pam::PAM_AUTHINFO_UNAVAIL);
+        assert!(ret.msg.contains("10.0.0.0/33"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_policy_tn_invalid_entry_audit() -> Result<()> {
+        let argv = args(&["lib", "--mode=audit", "--ip-allow=10.0.0.0/33"]);
+        let ret = load_policy(argv, pam::PAM_AUTHINFO_UNAVAIL).expect_err("must fail");
+
+        assert_eq!(ret.priority, LOG_WARNING);
+        assert_eq!(ret.code, pam::PAM_IGNORE);
+        assert!(ret.msg.contains("10.0.0.0/33"));
+
+        let argv = args(&[
+            "lib",
+            "--mode=audit",
+            "--audit-return=success",
+            "--on-config-error=perm-denied",
+            "--user-allow=bad user",
+        ]);
+        let ret = load_policy(argv, pam::PAM_AUTHINFO_UNAVAIL).expect_err("must fail");
+
+        assert_eq!(ret.code, pam::PAM_SUCCESS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_connection_failed_tp_on_resolver_error() -> Result<()> {
+        let enforce = policy(&["lib", "--on-resolver-error=ignore"])?;
+        let ret = connection_failed(&enforce, anyhow::anyhow!("null pamh passed"));
+
+        assert_eq!(ret.priority, LOG_ERR);
+        assert_eq!(ret.code, pam::PAM_IGNORE);
+
+        let audit = policy(&["lib", "--mode=audit", "--audit-return=success"])?;
+        let ret = connection_failed(&audit, anyhow::anyhow!("null pamh passed"));
+
+        assert_eq!(ret.priority, LOG_WARNING);
+        assert_eq!(ret.code, pam::PAM_SUCCESS);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+
+use crate::policy::Policy;
+
+/// Identity of a file a policy was built from. Replacing the file changes
+/// the inode, writing to it the size or modification time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSnapshot {
+    path: PathBuf,
+    // None if the file did not exist
+    id: Option<FileId>,
+}
+
+struct Entry {
+    files: Vec<FileSnapshot>,
+    policy: Arc<Policy>,
+}
+
+// compiled policies of this process keyed on the module arguments; a PAM
+// stack with several instances of the module keeps one entry per instance
+static CACHE: LazyLock<Mutex<HashMap<Vec<String>, Entry>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn file_id(path: &Path) -> Option<FileId> {
+    let meta = fs::metadata(path).ok()?;
+
+    Some(FileId {
+        dev: meta.dev(),
+        ino: meta.ino(),
+        size: meta.size(),
+        mtime: meta.mtime(),
+        mtime_nsec: meta.mtime_nsec(),
+    })
+}
+
+/// Takes the identity of `paths` before they are read, so that a change
+/// while the policy is built leaves an entry that is never hit.
+pub fn snapshot<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Vec<FileSnapshot> {
+    paths
+        .into_iter()
+        .map(|x| FileSnapshot {
+            path: x.as_ref().to_owned(),
+            id: file_id(x.as_ref()),
+        })
+        .collect()
+}
+
+/// The policy built for `args`, unless one of its files has changed since.
+pub fn get(args: &[String]) -> Option<Arc<Policy>> {
+    let cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    let entry = cache.get(args)?;
+
+    if entry.files.iter().any(|x| file_id(&x.path) != x.id) {
+        return None;
+    }
+
+    Some(Arc::clone(&entry.policy))
+}
+
+/// Stores `policy` for `args`, replacing an outdated entry.
+pub fn insert(args: Vec<String>, files: Vec<FileSnapshot>, policy: Policy) -> Arc<Policy> {
+    let policy = Arc::new(policy);
+    let entry = Entry {
+        files,
+        policy: Arc::clone(&policy),
+    };
+
+    CACHE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(args, entry);
+
+    policy
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::thread;
+
+    use anyhow::Result;
+
+    use crate::parser;
+    use crate::policy;
+
+    use super::*;
+
+    fn build(args: &[String]) -> Result<Policy> {
+        policy::policy_from_cli(parser::process_args(args.to_vec())?)
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_get_tp_hit() -> Result<()> {
+        let args = args(&["lib", "--ip-allow=192.0.2.1", "--user-allow=cache-hit"]);
+
+        assert!(get(&args).is_none());
+
+        let policy = insert(args.clone(), snapshot::<&Path>([]), build(&args)?);
+        let cached = get(&args).expect("must be cached");
+
+        assert!(Arc::ptr_eq(&policy, &cached));
+        assert!(get(&args[..2]).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_tn_config_changed() -> Result<()> {
+        let path = env::temp_dir().join(format!(
+            "pam-network-filter-cache-{}.conf",
+            std::process::id()
+        ));
+
+        fs::write(&path, "ip_allow = [\"192.0.2.0/24\"]\n")?;
+
+        let config = format!("--config={}", path.display());
+        let args = args(&["lib", &config]);
+
+        insert(args.clone(), snapshot([&path]), build(&args)?);
+        assert!(get(&args).is_some());
+
+        fs::write(&path, "ip_allow = [\"198.51.100.0/24\"]\n")?;
+        assert!(get(&args).is_none());
+
+        fs::remove_file(&path)?;
+        assert!(get(&args).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_tp_threads() -> Result<()> {
+        let args = args(&["lib", "--ip-allow=192.0.2.2", "--user-allow=cache-threads"]);
+
+        insert(args.clone(), snapshot::<&Path>([]), build(&args)?);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let args = args.clone();
+                thread::spawn(move || get(&args).is_some())
+            })
+            .collect();
+
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+
+        Ok(())
+    }
+}
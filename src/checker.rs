@@ -0,0 +1,294 @@
+use std::ffi::c_int;
+use std::process::ExitCode;
+
+use anyhow::Result;
+use clap::Parser;
+use jiff::Timestamp;
+
+use crate::auth::{self, Decision, Outcome};
+use crate::config;
+use crate::ffi::pam;
+use crate::item::{Connection, RemoteHost};
+use crate::log::Logger;
+use crate::parser;
+use crate::policy;
+
+/// Evaluates the module arguments for a synthetic login, e.g. in CI before a
+/// policy change is deployed. Exits with 0 on allow, 1 on deny, 2 on errors
+/// and 3 if no rule matched with --on-no-match set.
+#[derive(Parser, Debug)]
+#[command(name = "pam-network-filter-check", version, about)]
+pub struct CheckCli {
+    /// PAM_USER of the login
+    #[clap(long)]
+    pub user: String,
+
+    /// PAM_RHOST, an IP or a host name; empty for local logins
+    #[clap(long, default_value = "")]
+    pub rhost: String,
+
+    #[clap(long, default_value = "sshd")]
+    pub service: String,
+
+    #[clap(long, default_value = "")]
+    pub ruser: String,
+
+    #[clap(long, default_value = "")]
+    pub tty: String,
+
+    /// Listening port matched against --port-allow
+    #[clap(long)]
+    pub port: Option<u16>,
+
+    /// Time of the login instead of now, e.g. 2026-10-19T08:00:00Z
+    #[clap(long)]
+    pub at: Option<Timestamp>,
+
+    /// Module arguments, given after "--", e.g.
+    /// -- --config=/etc/security/network_filter.conf
+    #[clap(last = true, required = true)]
+    pub module_args: Vec<String>,
+}
+
+// messages of the single checks go to stderr, the decision to stdout
+struct StderrLogger;
+
+impl Logger for StderrLogger {
+    fn log(&self, _priority: c_int, msg: &str) {
+        eprintln!("{}", msg);
+    }
+}
+
+/// Runs the evaluation of `pam_sm_authenticate` for the login in `cli`.
+pub fn check(cli: CheckCli) -> Result<Decision> {
+    let mut args = vec![config::PAM_MODULE_LIB.to_owned()];
+    args.extend(cli.module_args);
+
+    let policy = policy::policy_from_cli(parser::process_args(args)?)?;
+
+    let conn = Connection {
+        service: cli.service,
+        user: cli.user,
+        ruser: cli.ruser,
+        remote: RemoteHost::parse(&cli.rhost),
+        rhost: cli.rhost,
+        tty: cli.tty,
+        client_port: None,
+        server_port: cli.port,
+    };
+    let now = cli.at.unwrap_or_else(Timestamp::now);
+
+    Ok(auth::decide(
+        &policy,
+        &conn,
+        now,
+        &StderrLogger,
+        pam::PAM_AUTH_ERR,
+    ))
+}
+
+pub fn main() -> ExitCode {
+    let decision = match check(CheckCli::parse()) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    println!("decision: {}", decision.decision.as_str());
+    println!("matched: {}", decision.matched);
+    println!("reason: {}", decision.reason);
+
+    match decision.decision {
+        Outcome::Allow => ExitCode::SUCCESS,
+        Outcome::Deny => ExitCode::from(1),
+        Outcome::NoMatch => ExitCode::from(3),
+        Outcome::Error => ExitCode::from(2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_args(args: &[&str]) -> Result<Decision> {
+        let mut argv = vec!["pam-network-filter-check"];
+        argv.extend(args);
+
+        check(CheckCli::try_parse_from(argv)?)
+    }
+
+    #[test]
+    fn test_check_tp_allow() -> Result<()> {
+        let ret = check_args(&[
+            "--user=root",
+            "--rhost=10.1.2.3",
+            "--",
+            "--ip-allow=10.0.0.0/8",
+        ])?;
+
+        assert_eq!(ret.decision, Outcome::Allow);
+        assert_eq!(ret.code, pam::PAM_SUCCESS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_tp_normalized_rhost() -> Result<()> {
+        for rhost in ["::ffff:10.1.2.3", "[::ffff:10.1.2.3]:22", "10.1.2.3:22"] {
+            let ret = check_args(&[
+                "--user=root",
+                &format!("--rhost={}", rhost),
+                "--",
+                "--ip-allow=10.0.0.0/8",
+                "--ip-deny=::ffff:0:0/96",
+            ])?;
+
+            assert_eq!(ret.decision, Outcome::Allow, "{}", rhost);
+        }
+
+        let ret = check_args(&[
+            "--user=root",
+            "--rhost=Runner1.CI.example.",
+            "--",
+            "--domain-deny=runner1.ci.example",
+        ])?;
+
+        assert_eq!(ret.decision, Outcome::Deny);
+        assert_eq!(
+            ret.reason,
+            "host 'runner1.ci.example' denied by domain-deny list"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_tp_resolve_rhost() -> Result<()> {
+        let args = ["--user=root", "--rhost=localhost", "--"];
+
+        let ret = check_args(&[&args[..], &["--ip-allow=127.0.0.0/8"]].concat())?;
+
+        // without --resolve-rhost a hostname is never in an IP list
+        assert_eq!(ret.decision, Outcome::Deny);
+        assert_eq!(ret.matched, "ip-allow");
+        assert_eq!(
+            ret.reason,
+            "host 'localhost' not allowed by ip-allow list (host is not an IP)"
+        );
+
+        let ret = check_args(
+            &[
+                &args[..],
+                &["--ip-allow=127.0.0.0/8,::1", "--resolve-rhost"],
+            ]
+            .concat(),
+        )?;
+
+        assert_eq!(ret.decision, Outcome::Allow);
+
+        let ret = check_args(
+            &[
+                &args[..],
+                &[
+                    "--ip-allow=127.0.0.0/8",
+                    "--resolve-rhost",
+                    "--resolve-match=any",
+                ],
+            ]
+            .concat(),
+        )?;
+
+        assert_eq!(ret.decision, Outcome::Allow);
+
+        let ret = check_args(
+            &[
+                &args[..],
+                &[
+                    "--ip-deny=127.0.0.1",
+                    "--resolve-rhost",
+                    "--resolve-match=any",
+                ],
+            ]
+            .concat(),
+        )?;
+
+        assert_eq!(ret.decision, Outcome::Deny);
+        assert_eq!(ret.matched, "ip-deny");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_tn_resolve_rhost_lookup_failed() -> Result<()> {
+        let ret = check_args(&[
+            "--user=root",
+            "--rhost=host.invalid",
+            "--",
+            "--ip-allow=10.0.0.0/8",
+            "--resolve-rhost",
+        ])?;
+
+        assert_eq!(ret.decision, Outcome::Error);
+        assert!(
+            ret.reason
+                .starts_with("forward lookup of 'host.invalid' failed")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_tp_matched_rule() -> Result<()> {
+        let ret = check_args(&[
+            "--user=root",
+            "--rhost=192.0.2.1",
+            "--",
+            "--rule=permit users=root hosts=10.0.0.0/8",
+            "--rule=deny users=root",
+        ])?;
+
+        assert_eq!(ret.decision, Outcome::Deny);
+        assert_eq!(ret.matched, "rule 2");
+        assert_eq!(ret.code, pam::PAM_AUTH_ERR);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_tp_at() -> Result<()> {
+        let args = [
+            "--user=root",
+            "--at=2026-10-24T10:00:00Z",
+            "--",
+            "--time-allow=Mon-Fri 08:00-19:00 UTC",
+        ];
+
+        let ret = check_args(&args)?;
+
+        assert_eq!(ret.decision, Outcome::Deny);
+        assert_eq!(ret.matched, "time-allow");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_tn_invalid_module_args() -> Result<()> {
+        let ret =
+            check_args(&["--user=root", "--", "--ip-allow=10.0.0.0/33"]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("10.0.0.0/33"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_tn_module_args_missing() -> Result<()> {
+        let ret = check_args(&["--user=root"]).expect_err("must fail");
+
+        assert!(ret.is::<clap::Error>());
+
+        Ok(())
+    }
+}
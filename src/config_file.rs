@@ -0,0 +1,370 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use clap::{CommandFactory, Parser};
+use toml::Spanned;
+use toml::de::{DeTable, DeValue};
+
+use crate::config;
+use crate::parser::Cli;
+use crate::policy;
+
+// 1-based line and column of a byte offset
+fn line_col(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+
+    (line, col)
+}
+
+fn located(name: &str, content: &str, span: Range<usize>, msg: &str) -> String {
+    let (line, col) = line_col(content, span.start);
+
+    format!("{}:{}:{}: {}", name, line, col, msg)
+}
+
+// span of the `part` at byte `offset` of the string `value` found at `span`;
+// the whole value if escapes keep the source from matching the string
+fn part_span(
+    content: &str,
+    span: Range<usize>,
+    value: &str,
+    offset: usize,
+    part: &str,
+) -> Range<usize> {
+    let quoted = content.get(span.start + 1..span.end.saturating_sub(1));
+
+    match quoted {
+        Some(x) if x == value => {
+            let start = span.start + 1 + offset;
+            start..start + part.len()
+        }
+        _ => span,
+    }
+}
+
+fn scalar_to_string(value: &DeValue) -> Option<String> {
+    match value {
+        DeValue::String(x) => Some(x.to_string()),
+        DeValue::Integer(x) => i64::from_str_radix(x.as_str(), x.radix())
+            .ok()
+            .map(|x| x.to_string()),
+        _ => None,
+    }
+}
+
+// converts one `key = value` pair into long options, e.g.
+// ip_allow = ["10.0.0.0/8", "::1"] => --ip-allow=10.0.0.0/8 --ip-allow=::1
+fn args_from_entry(
+    name: &str,
+    content: &str,
+    key: &Spanned<std::borrow::Cow<str>>,
+    value: &Spanned<DeValue>,
+) -> Result<Vec<String>> {
+    let option = key.get_ref().replace('_', "-");
+    let cmd = Cli::command();
+
+    let arg = match cmd
+        .get_arguments()
+        .find(|x| x.get_long() == Some(option.as_str()))
+    {
+        Some(x) if option != "config" => x,
+        _ => {
+            let msg = format!("unknown option '{}'", key.get_ref());
+            bail!(located(name, content, key.span(), &msg));
+        }
+    };
+
+    let mut args = Vec::new();
+
+    if !arg.get_action().takes_values() {
+        match value.get_ref() {
+            DeValue::Boolean(true) => args.push(format!("--{}", option)),
+            DeValue::Boolean(false) => {}
+            _ => {
+                let msg = format!("option '{}' expects true or false", key.get_ref());
+                bail!(located(name, content, value.span(), &msg));
+            }
+        }
+
+        return Ok(args);
+    }
+
+    let values: Vec<&Spanned<DeValue>> = match value.get_ref() {
+        DeValue::Array(x) => x.iter().collect(),
+        _ => vec![value],
+    };
+    let mut entries = Vec::new();
+
+    for value in values {
+        match scalar_to_string(value.get_ref()) {
+            Some(x) => {
+                args.push(format!("--{}={}", option, x));
+                entries.push((x, value.span()));
+            }
+            None => {
+                let msg = format!(
+                    "option '{}' does not accept a {}",
+                    key.get_ref(),
+                    value.get_ref().type_str()
+                );
+                bail!(located(name, content, value.span(), &msg));
+            }
+        }
+    }
+
+    if args.is_empty() {
+        return Ok(args);
+    }
+
+    // validate each option on its own so that errors can point at the value
+    let mut check = vec![config::PAM_MODULE_LIB.to_owned()];
+    check.extend(args.iter().cloned());
+
+    if let Err(e) = Cli::try_parse_from(check) {
+        let rendered = e.to_string();
+        let msg = rendered
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches("error: ");
+        bail!(located(name, content, value.span(), msg));
+    }
+
+    // entries the policy would reject, e.g. a wrong IP in a list, point at
+    // the array element, or at the part of a comma-joined string
+    for (entry, span) in entries {
+        let mut parts = Vec::new();
+        let mut offset = 0;
+
+        match arg.get_value_delimiter() {
+            Some(x) => {
+                for part in entry.split(x) {
+                    parts.push((offset, part));
+                    offset += part.len() + x.len_utf8();
+                }
+            }
+            None => parts.push((0, entry.as_str())),
+        }
+
+        for &(offset, part) in &parts {
+            if let Err(e) = policy::check_entry(&option, part) {
+                let span = match parts.len() {
+                    1 => span,
+                    _ => part_span(content, span, &entry, offset, part),
+                };
+                bail!(located(name, content, span, &format!("{:#}", e)));
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// Converts the content of a config file into module arguments.
+///
+/// Every top-level key names a long option of the module, written as either
+/// `ip_allow` or `ip-allow`. Strings and integers become a single value,
+/// arrays repeat the option for each element and booleans toggle flags.
+pub fn args_from_config(name: &str, content: &str) -> Result<Vec<String>> {
+    let table = match DeTable::parse(content) {
+        Ok(x) => x,
+        Err(e) => {
+            let span = e.span().unwrap_or(0..0);
+            bail!(located(name, content, span, e.message()));
+        }
+    };
+
+    let mut args = Vec::new();
+
+    for (key, value) in table.get_ref().iter() {
+        args.extend(args_from_entry(name, content, key, value)?);
+    }
+
+    Ok(args)
+}
+
+pub fn args_from_config_file(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("cannot read config file '{}'", path.display()))?;
+
+    args_from_config(&path.display().to_string(), &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_args_from_config_tp_lists_and_flags() -> Result<()> {
+        let content = r#"
+# networks allowed to log in
+ip_allow = ["10.0.0.0/8", "2001:db8::/32"]
+ip-deny = "10.0.66.0/24"
+port_allow = [22, "8000-8100"]
+fcrdns = true
+precedence = "allow"
+"#;
+
+        let args = args_from_config("test.conf", content)?;
+
+        assert!(args.contains(&"--ip-allow=10.0.0.0/8".to_owned()));
+        assert!(args.contains(&"--ip-allow=2001:db8::/32".to_owned()));
+        assert!(args.contains(&"--ip-deny=10.0.66.0/24".to_owned()));
+        assert!(args.contains(&"--port-allow=22".to_owned()));
+        assert!(args.contains(&"--port-allow=8000-8100".to_owned()));
+        assert!(args.contains(&"--fcrdns".to_owned()));
+        assert!(args.contains(&"--precedence=allow".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tp_flag_false() -> Result<()> {
+        let args = args_from_config("test.conf", "fcrdns = false")?;
+
+        assert!(args.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_syntax_error() -> Result<()> {
+        let content = "ip_allow = [\"10.0.0.1\"]\nuser_allow = [\"root\"\n";
+        let ret = args_from_config("test.conf", content).expect_err("must fail");
+
+        assert!(ret.to_string().starts_with("test.conf:"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_unknown_option() -> Result<()> {
+        let content = "ip_allow = []\n  ip_alow = [\"10.0.0.1\"]\n";
+        let ret = args_from_config("test.conf", content).expect_err("must fail");
+
+        assert_eq!(ret.to_string(), "test.conf:2:3: unknown option 'ip_alow'");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_nested_config() -> Result<()> {
+        let ret =
+            args_from_config("test.conf", "config = \"/etc/other.conf\"").expect_err("must fail");
+
+        assert!(ret.to_string().contains("unknown option 'config'"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_wrong_type() -> Result<()> {
+        let content = "user_allow = [\"root\", { name = \"doe\" }]";
+        let ret = args_from_config("test.conf", content).expect_err("must fail");
+
+        assert_eq!(
+            ret.to_string(),
+            "test.conf:1:23: option 'user_allow' does not accept a table"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_flag_with_value() -> Result<()> {
+        let ret = args_from_config("test.conf", "fcrdns = \"yes\"").expect_err("must fail");
+
+        assert_eq!(
+            ret.to_string(),
+            "test.conf:1:10: option 'fcrdns' expects true or false"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_invalid_entry() -> Result<()> {
+        let content =
+            "user_allow = \"root\"\nip_allow = [\n  \"10.0.0.0/8\",\n  \"10.0.0.0/33\",\n]\n";
+        let ret = args_from_config("test.conf", content).expect_err("must fail");
+
+        assert_eq!(
+            ret.to_string(),
+            "test.conf:4:3: '10.0.0.0/33' no matching pattern"
+        );
+
+        let content = "rule = [\"permit\", \"allow users=root\"]";
+        let ret = args_from_config("test.conf", content).expect_err("must fail");
+
+        assert_eq!(
+            ret.to_string(),
+            "test.conf:1:19: 'allow users=root' rule must start with permit or deny"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tp_comma_joined_entry() -> Result<()> {
+        let content = "ip_allow = \"10.0.0.1,10.0.0.2\"";
+        let ret = args_from_config("test.conf", content);
+
+        assert_eq!(ret?, vec!["--ip-allow=10.0.0.1,10.0.0.2"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_comma_joined_entry() -> Result<()> {
+        let content = "ip_allow = [\"10.0.0.1,10.0.0.0/33\"]";
+        let ret = args_from_config("test.conf", content).expect_err("must fail");
+
+        assert_eq!(
+            ret.to_string(),
+            "test.conf:1:23: '10.0.0.0/33' no matching pattern"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_tn_invalid_value() -> Result<()> {
+        let content = "\nprecedence = \"both\"";
+        let ret = args_from_config("test.conf", content).expect_err("must fail");
+
+        assert!(
+            ret.to_string()
+                .starts_with("test.conf:2:14: invalid value 'both'")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_file_tp_fixture() -> Result<()> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/config/network_filter.conf");
+
+        let args = args_from_config_file(&path)?;
+
+        assert!(args.contains(&"--user-deny=guest".to_owned()));
+        assert!(args.contains(&"--domain-allow=*.build.corp.example".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_args_from_config_file_tn_not_found() -> Result<()> {
+        let ret = args_from_config_file(Path::new("/nonexistent.conf")).expect_err("must fail");
+
+        assert!(ret.to_string().contains("cannot read config file"));
+
+        Ok(())
+    }
+}
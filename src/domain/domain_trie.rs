@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+// one node per label, keyed from the top-level label down, so that
+// "a.example.com" is stored as com -> example -> a
+#[derive(Debug, Default)]
+struct DomainNode {
+    children: HashMap<String, DomainNode>,
+    // the name ending at this node matches
+    exact: bool,
+    // any name strictly below this node matches
+    subdomains: bool,
+}
+
+/// Set of domain names supporting exact and suffix entries.
+///
+/// - `host.example.com` matches only itself
+/// - `*.example.com` matches any subdomain of `example.com`, but not
+///   `example.com` itself
+/// - `.example.com` matches `example.com` and any of its subdomains
+///
+/// Suffixes only match on label boundaries, so `.example.com` does not match
+/// `badexample.com`. Matching is case-insensitive and ignores a trailing dot.
+#[derive(Debug, Default)]
+pub struct DomainTrie {
+    root: DomainNode,
+    len: usize,
+}
+
+fn normalize(domain: &str) -> String {
+    domain
+        .strip_suffix('.')
+        .unwrap_or(domain)
+        .to_ascii_lowercase()
+}
+
+impl DomainTrie {
+    pub fn insert(&mut self, entry: &str) {
+        let entry = normalize(entry);
+
+        let (domain, exact, subdomains) = if let Some(x) = entry.strip_prefix("*.") {
+            (x, false, true)
+        } else if let Some(x) = entry.strip_prefix('.') {
+            (x, true, true)
+        } else {
+            (entry.as_str(), true, false)
+        };
+
+        let mut node = &mut self.root;
+
+        for label in domain.rsplit('.') {
+            node = node.children.entry(label.to_owned()).or_default();
+        }
+
+        node.exact |= exact;
+        node.subdomains |= subdomains;
+        self.len += 1;
+    }
+
+    pub fn contains(&self, domain: &str) -> bool {
+        let domain = normalize(domain);
+        let mut labels = domain.rsplit('.').peekable();
+        let mut node = &self.root;
+
+        while let Some(label) = labels.next() {
+            node = match node.children.get(label) {
+                Some(x) => x,
+                None => return false,
+            };
+
+            if node.subdomains && labels.peek().is_some() {
+                return true;
+            }
+        }
+
+        node.exact
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_trie_tp_exact() {
+        let mut trie = DomainTrie::default();
+        trie.insert("host.example.com");
+
+        assert!(trie.contains("host.example.com"));
+        assert!(trie.contains("HOST.Example.COM."));
+        assert!(!trie.contains("example.com"));
+        assert!(!trie.contains("a.host.example.com"));
+    }
+
+    #[test]
+    fn test_domain_trie_tp_wildcard() {
+        let mut trie = DomainTrie::default();
+        trie.insert("*.build.corp.example");
+
+        assert!(trie.contains("ci1.build.corp.example"));
+        assert!(trie.contains("a.b.build.corp.example"));
+        assert!(!trie.contains("build.corp.example"));
+        assert!(!trie.contains("corp.example"));
+    }
+
+    #[test]
+    fn test_domain_trie_tp_suffix() {
+        let mut trie = DomainTrie::default();
+        trie.insert(".corp.example");
+
+        assert!(trie.contains("corp.example"));
+        assert!(trie.contains("host.corp.example"));
+        assert!(trie.contains("a.b.corp.example"));
+    }
+
+    #[test]
+    fn test_domain_trie_tn_label_boundary() {
+        let mut trie = DomainTrie::default();
+        trie.insert(".corp.example");
+        trie.insert("*.build.example");
+
+        assert!(!trie.contains("badcorp.example"));
+        assert!(!trie.contains("corp.example.evil.com"));
+        assert!(!trie.contains("xbuild.example"));
+        assert!(!trie.contains("example"));
+    }
+
+    #[test]
+    fn test_domain_trie_tp_wildcard_and_exact_combined() {
+        let mut trie = DomainTrie::default();
+        trie.insert("*.corp.example");
+        trie.insert("corp.example");
+
+        assert!(trie.contains("corp.example"));
+        assert!(trie.contains("host.corp.example"));
+    }
+
+    #[test]
+    fn test_domain_trie_tp_many_entries() {
+        let mut trie = DomainTrie::default();
+
+        for i in 0..10_000 {
+            trie.insert(&format!("host{}.dc{}.corp.example", i, i % 10));
+        }
+
+        assert!(trie.contains("host9999.dc9.corp.example"));
+        assert!(!trie.contains("host9999.dc8.corp.example"));
+        assert!(!trie.is_empty());
+    }
+
+    #[test]
+    fn test_domain_trie_tn_empty() {
+        let trie = DomainTrie::default();
+
+        assert!(trie.is_empty());
+        assert!(!trie.contains("example.com"));
+    }
+}
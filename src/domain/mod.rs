@@ -2,7 +2,7 @@ use std::ffi::{c_char, c_int};
 use std::io::Error;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use libc;
 
 use crate::c_utils;
@@ -10,10 +10,12 @@ use crate::c_utils;
 use addrinfo_builder::AddrinfoBuilder;
 use addrinfo_smart_pointer::AddrinfoSmartPointer;
 
+pub use domain_trie::DomainTrie;
+
 mod addrinfo_builder;
 mod addrinfo_smart_pointer;
+mod domain_trie;
 
-#[allow(dead_code)]
 const NI_MAXHOST_USIZE: usize = libc::NI_MAXHOST as usize;
 
 #[allow(dead_code)]
@@ -24,7 +26,6 @@ pub enum AiFamily {
     AF_UNSPEC,
 }
 
-#[allow(dead_code)]
 fn eai_get_err_msg(err: c_int) -> String {
     return match err {
         libc::EAI_BADFLAGS => "EAI_BADFLAGS: addrinfo.ai_flags contains invalid flags".to_owned(),
@@ -46,7 +47,6 @@ fn eai_get_err_msg(err: c_int) -> String {
     };
 }
 
-#[allow(dead_code)]
 pub fn get_domain_from_ip(ip: IpAddr) -> Result<String> {
     let ip_nullterminated = format!("{}\0", ip.to_string());
     let node = ip_nullterminated.as_ptr() as *const c_char;
@@ -81,7 +81,6 @@ pub fn get_domain_from_ip(ip: IpAddr) -> Result<String> {
     Ok(c_utils::parse_c_string(host.as_ptr()))
 }
 
-#[allow(dead_code)]
 pub fn get_ip_from_domain(domain: &str, ai_family: AiFamily) -> Result<Vec<IpAddr>> {
     let domain_nullterminated = format!("{}\0", domain);
     let node = domain_nullterminated.as_ptr() as *const c_char;
@@ -139,6 +138,24 @@ pub fn get_ip_from_domain(domain: &str, ai_family: AiFamily) -> Result<Vec<IpAdd
     Ok(lookup)
 }
 
+/// Forward-confirmed reverse DNS: resolves `ip` to a name and accepts the
+/// name only if resolving it again yields `ip`. The error names the failed
+/// stage.
+pub fn get_verified_domain_from_ip(ip: IpAddr) -> Result<String> {
+    let domain =
+        get_domain_from_ip(ip).with_context(|| format!("reverse lookup of '{}' failed", ip))?;
+
+    let lookup = get_ip_from_domain(&domain, AiFamily::AF_UNSPEC)
+        .with_context(|| format!("forward lookup of '{}' failed", domain))?;
+
+    // compare IPv4-mapped IPv6 addresses as IPv4
+    if !lookup.iter().any(|x| x.to_canonical() == ip.to_canonical()) {
+        bail!("forward lookup of '{}' does not contain '{}'", domain, ip);
+    }
+
+    Ok(domain)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,4 +201,28 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_verified_domain_from_ip_tp_ipv4_localhost() -> Result<()> {
+        let ret = get_verified_domain_from_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        assert_eq!(ret?, "localhost");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_verified_domain_from_ip_tn_ipv4_0000_reverse_stage() -> Result<()> {
+        let ret =
+            get_verified_domain_from_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED)).expect_err("must fail");
+
+        assert!(error::is_underlying::<String>(&ret));
+        assert!(
+            ret.to_string()
+                .contains("reverse lookup of '0.0.0.0' failed")
+        );
+        assert!(format!("{:#}", ret).contains("EAI_NONAME"));
+
+        Ok(())
+    }
 }
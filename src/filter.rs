@@ -1,11 +1,18 @@
 use std::collections::HashSet;
 use std::marker::Sized;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
 use anyhow::{Result, bail};
+use glob::Pattern;
+use jiff::Timestamp;
+use jiff::tz::TimeZoneDatabase;
+use roaring::RoaringBitmap;
 
+use crate::domain::DomainTrie;
+use crate::neighbour::{self, MacAddr};
 use crate::network;
 use crate::pattern;
+use crate::time_window::{self, TimeWindow};
 
 pub trait Filter {
     type Value: ?Sized;
@@ -17,6 +24,7 @@ pub trait Filter {
 #[derive(Debug)]
 pub struct FilterIp {
     list_ipv4: network::Ipv4List,
+    list_ipv6: network::Ipv6List,
 }
 
 #[derive(Debug, Default)]
@@ -25,48 +33,62 @@ pub struct FilterUser {
 }
 
 #[derive(Debug, Default)]
-pub struct FilterDomain {
-    domains: HashSet<String>,
+pub struct FilterGroup {
+    groups: HashSet<String>,
 }
 
-impl Filter for FilterIp {
-    type Value = str;
+#[derive(Debug, Default)]
+pub struct FilterService {
+    services: HashSet<String>,
+}
 
-    fn contains(&self, rhost: &str) -> bool {
-        let ip = match rhost.parse::<Ipv4Addr>() {
-            Ok(x) => x,
-            Err(_) => return false,
-        };
+#[derive(Debug, Default)]
+pub struct FilterPort {
+    ports: RoaringBitmap,
+}
 
-        if self.list_ipv4.ips.contains(ip.to_bits()) {
-            return true;
-        }
+#[derive(Debug, Default)]
+pub struct FilterCountry {
+    countries: HashSet<String>,
+}
 
-        for subnet in &self.list_ipv4.subnets {
-            if subnet.contains(&ip) {
-                return true;
-            }
-        }
+#[derive(Debug, Default)]
+pub struct FilterAsn {
+    asns: RoaringBitmap,
+}
 
-        for range in &self.list_ipv4.ranges {
-            let (lower, upper) = &range;
+#[derive(Debug, Default)]
+pub struct FilterMac {
+    macs: HashSet<MacAddr>,
+}
 
-            if lower <= &ip && &ip <= upper {
-                return true;
-            }
-        }
+#[derive(Debug, Default)]
+pub struct FilterDomain {
+    domains: DomainTrie,
+}
+
+#[derive(Debug, Default)]
+pub struct FilterTty {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug, Default)]
+pub struct FilterTime {
+    windows: Vec<TimeWindow>,
+}
+
+impl Filter for FilterIp {
+    type Value = IpAddr;
 
-        false
+    fn contains(&self, ip: &IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => self.list_ipv4.contains(ip),
+            IpAddr::V6(ip) => self.list_ipv6.contains(ip),
+        }
     }
 
     fn is_empty(&self) -> bool {
-        let network::Ipv4List {
-            ips,
-            subnets,
-            ranges,
-        } = &self.list_ipv4;
-
-        ips.is_empty() && subnets.is_empty() && ranges.is_empty()
+        self.list_ipv4.is_empty() && self.list_ipv6.is_empty()
     }
 }
 
@@ -82,6 +104,84 @@ impl Filter for FilterUser {
     }
 }
 
+impl Filter for FilterGroup {
+    type Value = str;
+
+    fn contains(&self, group: &str) -> bool {
+        self.groups.contains(group)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+impl FilterGroup {
+    pub fn contains_any(&self, groups: &[String]) -> bool {
+        groups.iter().any(|x| self.groups.contains(x))
+    }
+}
+
+impl Filter for FilterService {
+    type Value = str;
+
+    fn contains(&self, service: &str) -> bool {
+        self.services.contains(service)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.services.is_empty()
+    }
+}
+
+impl Filter for FilterPort {
+    type Value = u16;
+
+    fn contains(&self, port: &u16) -> bool {
+        self.ports.contains(u32::from(*port))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ports.is_empty()
+    }
+}
+
+impl Filter for FilterCountry {
+    type Value = str;
+
+    fn contains(&self, country: &str) -> bool {
+        self.countries.contains(&country.to_ascii_uppercase())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.countries.is_empty()
+    }
+}
+
+impl Filter for FilterAsn {
+    type Value = u32;
+
+    fn contains(&self, asn: &u32) -> bool {
+        self.asns.contains(*asn)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.asns.is_empty()
+    }
+}
+
+impl Filter for FilterMac {
+    type Value = MacAddr;
+
+    fn contains(&self, mac: &MacAddr) -> bool {
+        self.macs.contains(mac)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.macs.is_empty()
+    }
+}
+
 impl Filter for FilterDomain {
     type Value = str;
 
@@ -94,10 +194,42 @@ impl Filter for FilterDomain {
     }
 }
 
+impl Filter for FilterTty {
+    type Value = str;
+
+    // PAM_TTY may or may not carry the /dev/ prefix
+    fn contains(&self, tty: &str) -> bool {
+        let tty = tty.strip_prefix("/dev/").unwrap_or(tty);
+
+        self.patterns.iter().any(|x| x.matches(tty))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+impl Filter for FilterTime {
+    type Value = Timestamp;
+
+    fn contains(&self, now: &Timestamp) -> bool {
+        self.windows.iter().any(|x| x.contains(*now))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+}
+
 pub fn filter_from_ips(ips: Vec<String>) -> Result<FilterIp> {
-    let list_ipv4 = network::create_list_ipv4(ips)?;
+    let (ipv4, ipv6) = network::split_by_family(ips)?;
+    let list_ipv4 = network::create_list_ipv4(ipv4)?;
+    let list_ipv6 = network::create_list_ipv6(ipv6)?;
 
-    Ok(FilterIp { list_ipv4 })
+    Ok(FilterIp {
+        list_ipv4,
+        list_ipv6,
+    })
 }
 
 pub fn filter_from_users(users: Vec<String>) -> Result<FilterUser> {
@@ -115,13 +247,117 @@ pub fn filter_from_users(users: Vec<String>) -> Result<FilterUser> {
     Ok(filter)
 }
 
+pub fn filter_from_groups(groups: Vec<String>) -> Result<FilterGroup> {
+    let mut filter = FilterGroup::default();
+    let pat_groupname = pattern::pat_groupname();
+
+    for group in groups {
+        if pat_groupname.is_match(&group)? {
+            filter.groups.insert(group);
+        } else {
+            bail!("'{}' wrong group name syntax", group);
+        }
+    }
+
+    Ok(filter)
+}
+
+pub fn filter_from_services(services: Vec<String>) -> Result<FilterService> {
+    let mut filter = FilterService::default();
+    let pat_service = pattern::pat_service();
+
+    for service in services {
+        if pat_service.is_match(&service)? {
+            filter.services.insert(service);
+        } else {
+            bail!("'{}' wrong service syntax", service);
+        }
+    }
+
+    Ok(filter)
+}
+
+pub fn filter_from_ports(ports: Vec<String>) -> Result<FilterPort> {
+    let mut filter = FilterPort::default();
+    let pat_port = pattern::pat_port();
+    let pat_port_range = pattern::pat_port_range();
+
+    for port in ports {
+        if pat_port.is_match(&port)? {
+            filter.ports.insert(u32::from(port.parse::<u16>()?));
+        } else if pat_port_range.is_match(&port)? {
+            let (lower, upper) = port.split_once('-').unwrap();
+            let lower = lower.parse::<u16>()?;
+            let upper = upper.parse::<u16>()?;
+
+            if lower >= upper {
+                bail!(
+                    "'{}' port on left side should be lower than the right one",
+                    port
+                );
+            }
+
+            filter
+                .ports
+                .insert_range(u32::from(lower)..=u32::from(upper));
+        } else {
+            bail!("'{}' wrong port syntax", port);
+        }
+    }
+
+    Ok(filter)
+}
+
+pub fn filter_from_countries(countries: Vec<String>) -> Result<FilterCountry> {
+    let mut filter = FilterCountry::default();
+    let pat_country = pattern::pat_country();
+
+    for country in countries {
+        if pat_country.is_match(&country)? {
+            filter.countries.insert(country.to_ascii_uppercase());
+        } else {
+            bail!("'{}' wrong country code syntax", country);
+        }
+    }
+
+    Ok(filter)
+}
+
+pub fn filter_from_asns(asns: Vec<String>) -> Result<FilterAsn> {
+    let mut filter = FilterAsn::default();
+    let pat_asn = pattern::pat_asn();
+
+    for asn in asns {
+        if !pat_asn.is_match(&asn)? {
+            bail!("'{}' wrong ASN syntax", asn);
+        }
+
+        // "AS64496" and "64496" are the same
+        let number = asn.trim_start_matches(|x: char| x.is_ascii_alphabetic());
+        filter.asns.insert(number.parse::<u32>()?);
+    }
+
+    Ok(filter)
+}
+
+pub fn filter_from_macs(macs: Vec<String>) -> Result<FilterMac> {
+    let mut filter = FilterMac::default();
+
+    for mac in macs {
+        filter.macs.insert(neighbour::parse_mac(&mac)?);
+    }
+
+    Ok(filter)
+}
+
 pub fn filter_from_domains(domains: Vec<String>) -> Result<FilterDomain> {
     let mut filter = FilterDomain::default();
-    let pat_fqdn = pattern::pat_fqdn();
+    let pat_fqdn = pattern::pat_fqdn_wildcard();
 
     for domain in domains {
-        if pat_fqdn.is_match(&domain)? {
-            filter.domains.insert(domain);
+        // domain names are case-insensitive
+        if pat_fqdn.is_match(&domain.to_ascii_lowercase())? {
+            filter.domains.insert(&domain);
         } else {
             bail!("'{}' wrong domain syntax", domain);
         }
@@ -129,3 +365,212 @@ pub fn filter_from_domains(domains: Vec<String>) -> Result<FilterDomain> {
 
     Ok(filter)
 }
+
+pub fn filter_from_ttys(ttys: Vec<String>) -> Result<FilterTty> {
+    let mut filter = FilterTty::default();
+
+    for tty in ttys {
+        match Pattern::new(tty.strip_prefix("/dev/").unwrap_or(&tty)) {
+            Ok(x) => filter.patterns.push(x),
+            Err(e) => bail!("'{}' wrong tty pattern syntax: {}", tty, e.msg),
+        }
+    }
+
+    Ok(filter)
+}
+
+pub fn filter_from_times(times: Vec<String>, tzdb: &TimeZoneDatabase) -> Result<FilterTime> {
+    let mut filter = FilterTime::default();
+
+    for time in times {
+        filter
+            .windows
+            .push(time_window::parse_time_window(&time, tzdb)?);
+    }
+
+    Ok(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::ParseIntError;
+
+    use crate::error;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_from_groups_tp_contains_any() -> Result<()> {
+        let filter = filter_from_groups(vec!["wheel".to_owned(), "Domain Admins".to_owned()])?;
+
+        assert!(filter.contains_any(&["users".to_owned(), "Domain Admins".to_owned()]));
+        assert!(!filter.contains_any(&["users".to_owned(), "domain admins".to_owned()]));
+        assert!(!filter.contains_any(&[]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_groups_tn_syntax() -> Result<()> {
+        let ret = filter_from_groups(vec!["wheel:x".to_owned()]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("wrong group name syntax"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_services_tn_syntax() -> Result<()> {
+        let ret = filter_from_services(vec!["../sshd".to_owned()]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("wrong service syntax"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_ttys_tp_glob() -> Result<()> {
+        let filter = filter_from_ttys(vec![
+            "tty[1-6]".to_owned(),
+            "/dev/ttyS*".to_owned(),
+            ":0".to_owned(),
+        ])?;
+
+        assert!(filter.contains("tty1"));
+        assert!(filter.contains("/dev/tty6"));
+        assert!(filter.contains("ttyS0"));
+        assert!(filter.contains(":0"));
+        assert!(!filter.contains("tty7"));
+        assert!(!filter.contains("pts/0"));
+        assert!(!filter.contains("ssh"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_ttys_tn_syntax() -> Result<()> {
+        let ret = filter_from_ttys(vec!["tty[1-".to_owned()]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("wrong tty pattern syntax"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_countries_tp_case_insensitive() -> Result<()> {
+        let filter = filter_from_countries(vec!["de".to_owned(), "FR".to_owned()])?;
+
+        assert!(filter.contains("DE"));
+        assert!(filter.contains("fr"));
+        assert!(!filter.contains("US"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_countries_tn_syntax() -> Result<()> {
+        let ret = filter_from_countries(vec!["DEU".to_owned()]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("wrong country code syntax"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_asns_tp_prefix() -> Result<()> {
+        let filter = filter_from_asns(vec!["AS64496".to_owned(), "64500".to_owned()])?;
+
+        assert!(filter.contains(&64496));
+        assert!(filter.contains(&64500));
+        assert!(!filter.contains(&64511));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_asns_tn_out_of_range() -> Result<()> {
+        let ret = filter_from_asns(vec!["AS4294967296".to_owned()]).expect_err("must fail");
+
+        assert!(error::is_underlying::<ParseIntError>(&ret));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_macs_tp_case_insensitive() -> Result<()> {
+        let filter = filter_from_macs(vec!["AA:BB:CC:DD:EE:FF".to_owned()])?;
+
+        assert!(filter.contains(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]));
+        assert!(!filter.contains(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0x00]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_domains_tp_wildcard_and_suffix() -> Result<()> {
+        let filter = filter_from_domains(vec![
+            "*.build.corp.example".to_owned(),
+            ".VPN.corp.example".to_owned(),
+            "bastion.corp.example".to_owned(),
+        ])?;
+
+        assert!(filter.contains("ci7.build.corp.example"));
+        assert!(filter.contains("vpn.corp.example"));
+        assert!(filter.contains("gw.vpn.corp.example"));
+        assert!(filter.contains("Bastion.Corp.Example"));
+        assert!(!filter.contains("build.corp.example"));
+        assert!(!filter.contains("corp.example"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_domains_tn_wildcard_in_middle() -> Result<()> {
+        let ret = filter_from_domains(vec!["host.*.example".to_owned()]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("wrong domain syntax"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_ports_tp_single_and_range() -> Result<()> {
+        let filter = filter_from_ports(vec!["2222".to_owned(), "8000-8100".to_owned()])?;
+
+        assert!(filter.contains(&2222));
+        assert!(filter.contains(&8000));
+        assert!(filter.contains(&8050));
+        assert!(filter.contains(&8100));
+        assert!(!filter.contains(&22));
+        assert!(!filter.contains(&8101));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_ports_tn_out_of_range() -> Result<()> {
+        let ret = filter_from_ports(vec!["65536".to_owned()]).expect_err("must fail");
+
+        assert!(error::is_underlying::<ParseIntError>(&ret));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_ports_tn_range_invalid() -> Result<()> {
+        let ret = filter_from_ports(vec!["8100-8000".to_owned()]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("port on left side"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_from_ports_tn_syntax() -> Result<()> {
+        let ret = filter_from_ports(vec!["ssh".to_owned()]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("wrong port syntax"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use maxminddb::{Reader, geoip2};
+
+pub type GeoDb = Reader<Vec<u8>>;
+
+/// Reads a MaxMind database such as GeoLite2-Country.mmdb into memory. No
+/// network access is involved in opening or querying it.
+pub fn open_db(path: &Path) -> Result<GeoDb> {
+    Reader::open_readfile(path)
+        .with_context(|| format!("cannot open MaxMind database '{}'", path.display()))
+}
+
+/// ISO 3166-1 alpha-2 code of `ip`, falling back to the country the network
+/// is registered in when the location is unknown.
+pub fn lookup_country(db: &GeoDb, ip: IpAddr) -> Result<Option<String>> {
+    let Some(record) = db.lookup(ip)?.decode::<geoip2::Country>()? else {
+        return Ok(None);
+    };
+
+    let code = record
+        .country
+        .iso_code
+        .or(record.registered_country.iso_code);
+
+    Ok(code.map(str::to_ascii_uppercase))
+}
+
+pub fn lookup_asn(db: &GeoDb, ip: IpAddr) -> Result<Option<u32>> {
+    let record = db.lookup(ip)?.decode::<geoip2::Asn>()?;
+
+    Ok(record.and_then(|x| x.autonomous_system_number))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/geoip")
+            .join(name)
+    }
+
+    #[test]
+    fn test_lookup_country_tp_ipv4_and_ipv6() -> Result<()> {
+        let db = open_db(&fixture("country.mmdb"))?;
+
+        assert_eq!(
+            lookup_country(&db, "192.0.2.77".parse()?)?,
+            Some("DE".to_owned())
+        );
+        assert_eq!(
+            lookup_country(&db, "198.51.100.1".parse()?)?,
+            Some("US".to_owned())
+        );
+        assert_eq!(
+            lookup_country(&db, "2001:db8:ffff::1".parse()?)?,
+            Some("FR".to_owned())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_country_tn_not_found() -> Result<()> {
+        let db = open_db(&fixture("country.mmdb"))?;
+
+        assert_eq!(lookup_country(&db, "198.51.100.200".parse()?)?, None);
+        assert_eq!(lookup_country(&db, "203.0.113.1".parse()?)?, None);
+        assert_eq!(lookup_country(&db, "2001:db9::1".parse()?)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_asn_tp() -> Result<()> {
+        let db = open_db(&fixture("asn.mmdb"))?;
+
+        assert_eq!(lookup_asn(&db, "192.0.2.1".parse()?)?, Some(64496));
+        assert_eq!(lookup_asn(&db, "198.51.100.200".parse()?)?, Some(64500));
+        assert_eq!(lookup_asn(&db, "2001:db8:1:2::1".parse()?)?, Some(64511));
+        assert_eq!(lookup_asn(&db, "2001:db8:2::1".parse()?)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_db_tn_not_found() -> Result<()> {
+        let ret = open_db(Path::new("/nonexistent.mmdb")).expect_err("must fail");
+
+        assert_eq!(
+            ret.to_string(),
+            "cannot open MaxMind database '/nonexistent.mmdb'"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_db_tn_not_mmdb() -> Result<()> {
+        let ret = open_db(&fixture("generate.py")).expect_err("must fail");
+
+        assert!(ret.to_string().contains("cannot open MaxMind database"));
+
+        Ok(())
+    }
+}
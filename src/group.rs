@@ -0,0 +1,157 @@
+use std::ffi::{CString, c_char, c_int};
+use std::io::Error;
+
+use anyhow::{Result, bail};
+use libc;
+
+use crate::c_utils;
+
+// fallback when sysconf does not report a buffer size
+const DEFAULT_BUF_SIZE: usize = 16384;
+// upper bound for retries with a doubled buffer on ERANGE
+const MAX_BUF_SIZE: usize = 1 << 20;
+
+fn initial_buf_size(name: c_int) -> usize {
+    match unsafe { libc::sysconf(name) } {
+        x if x > 0 => x as usize,
+        _ => DEFAULT_BUF_SIZE,
+    }
+}
+
+fn get_primary_gid(user: &str) -> Result<libc::gid_t> {
+    let user_c = CString::new(user)?;
+    let mut buf: Vec<c_char> = vec![0; initial_buf_size(libc::_SC_GETPW_R_SIZE_MAX)];
+
+    loop {
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+        let ret = unsafe {
+            libc::getpwnam_r(
+                user_c.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+
+        if ret == libc::ERANGE && buf.len() < MAX_BUF_SIZE {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+
+        if ret != 0 {
+            bail!(
+                "getpwnam_r for user '{}' failed: {}",
+                user,
+                Error::from_raw_os_error(ret)
+            );
+        }
+
+        if result.is_null() {
+            bail!("user '{}' not found", user);
+        }
+
+        return Ok(pwd.pw_gid);
+    }
+}
+
+fn get_group_name(gid: libc::gid_t) -> Result<Option<String>> {
+    let mut buf: Vec<c_char> = vec![0; initial_buf_size(libc::_SC_GETGR_R_SIZE_MAX)];
+
+    loop {
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let mut result: *mut libc::group = std::ptr::null_mut();
+
+        let ret =
+            unsafe { libc::getgrgid_r(gid, &mut grp, buf.as_mut_ptr(), buf.len(), &mut result) };
+
+        if ret == libc::ERANGE && buf.len() < MAX_BUF_SIZE {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+
+        if ret != 0 {
+            bail!(
+                "getgrgid_r for gid {} failed: {}",
+                gid,
+                Error::from_raw_os_error(ret)
+            );
+        }
+
+        // a gid without a group entry has no name to match against
+        if result.is_null() {
+            return Ok(None);
+        }
+
+        return Ok(Some(c_utils::parse_c_string(grp.gr_name)));
+    }
+}
+
+/// Names of the primary and supplementary groups of `user` as reported by
+/// NSS, so LDAP and SSSD groups are included as well.
+pub fn get_user_groups(user: &str) -> Result<Vec<String>> {
+    let primary_gid = get_primary_gid(user)?;
+    let user_c = CString::new(user)?;
+    let mut ngroups: c_int = 32;
+    let mut gids: Vec<libc::gid_t> = vec![0; ngroups as usize];
+
+    loop {
+        let len = ngroups;
+        let ret = unsafe {
+            libc::getgrouplist(
+                user_c.as_ptr(),
+                primary_gid,
+                gids.as_mut_ptr(),
+                &mut ngroups,
+            )
+        };
+
+        if ret >= 0 {
+            break;
+        }
+
+        // ngroups now holds the required size
+        if ngroups <= len {
+            bail!("getgrouplist for user '{}' failed", user);
+        }
+
+        gids.resize(ngroups as usize, 0);
+    }
+
+    gids.truncate(ngroups as usize);
+
+    let mut groups = Vec::with_capacity(gids.len());
+
+    for gid in gids {
+        if let Some(name) = get_group_name(gid)? {
+            groups.push(name);
+        }
+    }
+
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_user_groups_tp_root() -> Result<()> {
+        let ret = get_user_groups("root");
+
+        assert!(ret?.iter().any(|x| x == "root"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_user_groups_tn_unknown_user() -> Result<()> {
+        let ret = get_user_groups("no-such-user-pnf").expect_err("must fail");
+
+        assert_eq!(ret.to_string(), "user 'no-such-user-pnf' not found");
+
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
-use std::ffi::{c_char, c_int, c_void};
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::fmt;
+use std::net::IpAddr;
 
 use anyhow::{Result, bail};
 use libc;
@@ -15,7 +17,99 @@ pub struct Connection {
     pub service: String,
     pub user: String,
     pub ruser: String,
+    // PAM_RHOST as given by the application, only used for logging
     pub rhost: String,
+    // PAM_RHOST as the filters match it
+    pub remote: RemoteHost,
+    // empty for sessions without a terminal
+    pub tty: String,
+    // PAM items do not carry ports; these come from the SSH environment
+    pub client_port: Option<u16>,
+    pub server_port: Option<u16>,
+}
+
+/// Remote host of a login in the form every filter matches against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteHost {
+    // PAM_RHOST not set or empty, e.g. a console or su login
+    Local,
+    Ip(IpAddr),
+    // lowercase, without a trailing dot
+    Name(String),
+}
+
+impl RemoteHost {
+    /// Normalizes a raw PAM_RHOST. IPv4-mapped IPv6 addresses become IPv4;
+    /// brackets, IPv6 scopes and ports are removed.
+    pub fn parse(rhost: &str) -> RemoteHost {
+        let rhost = rhost.trim();
+
+        if rhost.is_empty() {
+            return RemoteHost::Local;
+        }
+
+        if let Some(ip) = parse_ip(rhost) {
+            return RemoteHost::Ip(ip);
+        }
+
+        // "host:port"; an IPv6 address with a port is always bracketed
+        let host = match rhost.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => host,
+            _ => rhost,
+        };
+
+        if let Some(ip) = parse_ip(host) {
+            return RemoteHost::Ip(ip);
+        }
+
+        let name = host.strip_suffix('.').unwrap_or(host);
+        RemoteHost::Name(name.to_ascii_lowercase())
+    }
+
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            RemoteHost::Ip(ip) => Some(*ip),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RemoteHost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemoteHost::Local => Ok(()),
+            RemoteHost::Ip(ip) => write!(f, "{}", ip),
+            RemoteHost::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// "10.0.0.1", "::ffff:10.0.0.1", "[2001:db8::1]", "[2001:db8::1]:22" or
+// "fe80::1%eth0"
+fn parse_ip(host: &str) -> Option<IpAddr> {
+    let host = match host.strip_prefix('[') {
+        Some(rest) => {
+            let (addr, port) = rest.split_once(']')?;
+
+            if !port.is_empty() && port.strip_prefix(':')?.parse::<u16>().is_err() {
+                return None;
+            }
+
+            addr
+        }
+        None => host,
+    };
+
+    // the scope of a link-local address does not take part in matching
+    let host = match host.split_once('%') {
+        Some((addr, _)) if addr.contains(':') => addr,
+        _ => host,
+    };
+
+    match host.parse::<IpAddr>().ok()? {
+        IpAddr::V6(ip) => Some(ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4)),
+        ip => Some(ip),
+    }
 }
 
 fn pam_get_err_msg(val: c_int) -> String {
@@ -66,6 +160,42 @@ fn pam_item_log_err_and_throw(
     bail!(msg)
 }
 
+fn pam_get_env(pamh: *const pam::pam_handle_t, name: &CStr) -> Option<String> {
+    let value = unsafe { pam::pam_getenv(pamh as *mut pam::pam_handle_t, name.as_ptr()) };
+
+    if value.is_null() {
+        None
+    } else {
+        Some(parse_c_string(value))
+    }
+}
+
+// SSH_CONNECTION: "<client ip> <client port> <server ip> <server port>"
+// SSH_CLIENT:     "<client ip> <client port> <server port>"
+// returns client and server port in that order
+fn parse_ssh_ports(
+    ssh_connection: Option<&str>,
+    ssh_client: Option<&str>,
+) -> (Option<u16>, Option<u16>) {
+    if let Some(value) = ssh_connection {
+        let fields: Vec<_> = value.split_whitespace().collect();
+
+        if let [_, client_port, _, server_port] = fields[..] {
+            return (client_port.parse().ok(), server_port.parse().ok());
+        }
+    }
+
+    if let Some(value) = ssh_client {
+        let fields: Vec<_> = value.split_whitespace().collect();
+
+        if let [_, client_port, server_port] = fields[..] {
+            return (client_port.parse().ok(), server_port.parse().ok());
+        }
+    }
+
+    (None, None)
+}
+
 pub fn get_pam_connection(pamh: *const pam::pam_handle_t) -> Result<Connection> {
     if pamh.is_null() {
         bail!("null pamh passed");
@@ -99,11 +229,31 @@ pub fn get_pam_connection(pamh: *const pam::pam_handle_t) -> Result<Connection>
         parse_c_string(item as *const c_char)
     };
 
+    let ret = unsafe { pam::pam_get_item(pamh, pam::PAM_TTY, &mut item) };
+    pam_item_log_err_and_throw(pamh, ret, pam::PAM_TTY)?;
+
+    let tty = if item.is_null() {
+        String::new()
+    } else {
+        parse_c_string(item as *const c_char)
+    };
+
+    let (client_port, server_port) = parse_ssh_ports(
+        pam_get_env(pamh, c"SSH_CONNECTION").as_deref(),
+        pam_get_env(pamh, c"SSH_CLIENT").as_deref(),
+    );
+
+    let remote = RemoteHost::parse(&rhost);
+
     Ok(Connection {
         service,
         user,
         ruser,
         rhost,
+        remote,
+        tty,
+        client_port,
+        server_port,
     })
 }
 
@@ -147,6 +297,10 @@ mod tests {
         assert_eq!(connection.user, "doe");
         assert_eq!(connection.ruser, "");
         assert_eq!(connection.rhost, "");
+        assert_eq!(connection.remote, RemoteHost::Local);
+        assert_eq!(connection.tty, "");
+        assert_eq!(connection.client_port, None);
+        assert_eq!(connection.server_port, None);
 
         let ret = unsafe { pam::pam_end(pamh, pam::PAM_SUCCESS) };
 
@@ -182,6 +336,10 @@ mod tests {
         };
         assert_eq!(ret, pam::PAM_SUCCESS);
 
+        let ret =
+            unsafe { pam::pam_set_item(pamh, pam::PAM_TTY, c"ssh".as_ptr() as *const c_void) };
+        assert_eq!(ret, pam::PAM_SUCCESS);
+
         let ret = get_pam_connection(pamh);
         assert!(ret.is_ok());
 
@@ -194,6 +352,8 @@ mod tests {
         assert_eq!(connection.user, "doe");
         assert_eq!(connection.ruser, "hyundeok");
         assert_eq!(connection.rhost, "localhost");
+        assert_eq!(connection.remote, RemoteHost::Name("localhost".to_owned()));
+        assert_eq!(connection.tty, "ssh");
 
         let ret = unsafe { pam::pam_end(pamh, pam::PAM_SUCCESS) };
 
@@ -209,4 +369,123 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_pam_connection_tp_ssh_connection() {
+        let conv: pam::pam_conv = pam::pam_conv::default();
+        let mut pamh: *mut pam::pam_handle_t = std::ptr::null_mut();
+
+        let ret = unsafe {
+            pam::pam_start(
+                config::PAM_MODULE_NAME.as_ptr(),
+                c"doe".as_ptr(),
+                &conv,
+                &mut pamh,
+            )
+        };
+        assert_eq!(ret, pam::PAM_SUCCESS);
+
+        let ret = unsafe {
+            pam::pam_putenv(
+                pamh,
+                c"SSH_CONNECTION=10.0.0.2 51234 10.0.0.1 2222".as_ptr(),
+            )
+        };
+        assert_eq!(ret, pam::PAM_SUCCESS);
+
+        let ret = get_pam_connection(pamh);
+        assert!(ret.is_ok());
+
+        let connection = ret.unwrap();
+
+        assert_eq!(connection.client_port, Some(51234));
+        assert_eq!(connection.server_port, Some(2222));
+
+        let ret = unsafe { pam::pam_end(pamh, pam::PAM_SUCCESS) };
+
+        assert_eq!(ret, pam::PAM_SUCCESS);
+    }
+
+    #[test]
+    fn test_parse_ssh_ports_tp_ssh_client() {
+        let ret = parse_ssh_ports(None, Some("2001:db8::2 51234 22"));
+
+        assert_eq!(ret, (Some(51234), Some(22)));
+    }
+
+    #[test]
+    fn test_parse_ssh_ports_tp_ssh_connection_preferred() {
+        let ret = parse_ssh_ports(
+            Some("10.0.0.2 51234 10.0.0.1 2222"),
+            Some("10.0.0.2 51234 22"),
+        );
+
+        assert_eq!(ret, (Some(51234), Some(2222)));
+    }
+
+    #[test]
+    fn test_parse_ssh_ports_tn_malformed() {
+        assert_eq!(parse_ssh_ports(Some("10.0.0.2 51234"), None), (None, None));
+        assert_eq!(
+            parse_ssh_ports(Some("10.0.0.2 51234 10.0.0.1 70000"), None),
+            (Some(51234), None)
+        );
+        assert_eq!(parse_ssh_ports(None, None), (None, None));
+    }
+
+    #[test]
+    fn test_remote_host_parse_tp_ip() {
+        let cases = [
+            ("10.0.0.1", "10.0.0.1"),
+            ("::ffff:10.0.0.1", "10.0.0.1"),
+            ("[2001:db8::1]", "2001:db8::1"),
+            ("[2001:db8::1]:22", "2001:db8::1"),
+            ("[::ffff:10.0.0.1]:22", "10.0.0.1"),
+            ("fe80::1%eth0", "fe80::1"),
+            ("[fe80::1%eth0]:22", "fe80::1"),
+            ("10.0.0.1:2222", "10.0.0.1"),
+            ("2001:db8::1:22", "2001:db8::1:22"),
+            (" 10.0.0.1 ", "10.0.0.1"),
+        ];
+
+        for (rhost, ip) in cases {
+            let ip: IpAddr = ip.parse().unwrap();
+
+            assert_eq!(RemoteHost::parse(rhost), RemoteHost::Ip(ip), "{}", rhost);
+        }
+    }
+
+    #[test]
+    fn test_remote_host_parse_tp_name() {
+        let cases = [
+            ("host.corp.example", "host.corp.example"),
+            ("Host.Corp.Example.", "host.corp.example"),
+            ("host.corp.example:22", "host.corp.example"),
+            ("localhost", "localhost"),
+        ];
+
+        for (rhost, name) in cases {
+            let expected = RemoteHost::Name(name.to_owned());
+
+            assert_eq!(RemoteHost::parse(rhost), expected, "{}", rhost);
+        }
+    }
+
+    #[test]
+    fn test_remote_host_parse_tn_not_an_ip() {
+        assert_eq!(RemoteHost::parse(""), RemoteHost::Local);
+        assert_eq!(RemoteHost::parse("  "), RemoteHost::Local);
+        assert_eq!(
+            RemoteHost::parse("[2001:db8::1]:port"),
+            RemoteHost::Name("[2001:db8::1]:port".to_owned())
+        );
+        assert_eq!(
+            RemoteHost::parse("10.0.0.1:70000"),
+            RemoteHost::Name("10.0.0.1:70000".to_owned())
+        );
+        assert_eq!(
+            RemoteHost::parse("host%eth0"),
+            RemoteHost::Name("host%eth0".to_owned())
+        );
+    }
 }
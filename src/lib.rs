@@ -7,16 +7,27 @@ mod macros;
 
 mod auth;
 mod c_utils;
+mod cache;
+pub mod checker;
 mod config;
+mod config_file;
 mod domain;
 mod error;
 mod ffi;
 mod filter;
+mod geoip;
+mod group;
 mod item;
+pub mod lint;
+mod list_file;
 mod log;
+mod neighbour;
 mod network;
 mod parser;
 mod pattern;
+mod policy;
+mod rule;
+mod time_window;
 
 #[unsafe(no_mangle)]
 pub extern "C" fn pam_sm_authenticate(
@@ -41,12 +52,11 @@ pub extern "C" fn pam_sm_setcred(
 #[unsafe(no_mangle)]
 pub extern "C" fn pam_sm_acct_mgmt(
     pamh: *mut pam::pam_handle_t,
-    _flags: c_int,
-    _argc: c_int,
-    _argv: *const *const c_char,
+    flags: c_int,
+    argc: c_int,
+    argv: *const *const c_char,
 ) -> c_int {
-    log::log_unimplemented_pam_function(pamh, "pam_sm_acct_mgmt");
-    pam::PAM_IGNORE
+    auth::acct_mgmt(pamh, flags, argc, argv)
 }
 
 #[unsafe(no_mangle)]
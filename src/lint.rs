@@ -0,0 +1,678 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::Result;
+use clap::Parser;
+use ipnet::IpNet;
+
+use crate::config;
+use crate::list_file;
+use crate::log;
+use crate::network::{self, Intervals, IpBounds};
+use crate::parser::{self, Cli, LogFormat, Precedence};
+use crate::policy;
+
+/// Reports duplicate, shadowed and suspicious entries in the module
+/// arguments. Exits with 0 if there are no warnings or errors, 1 if there
+/// are and 2 if the arguments are invalid.
+#[derive(Parser, Debug)]
+#[command(name = "pam-network-filter-lint", version, about)]
+pub struct LintCli {
+    /// Layout of the findings, one per line
+    #[clap(long, value_enum, default_value_t = LogFormat::Json)]
+    pub format: LogFormat,
+
+    /// Module arguments, given after "--", e.g.
+    /// -- --config=/etc/security/network_filter.conf
+    #[clap(last = true, required = true)]
+    pub module_args: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    /// stable name of the check, e.g. `contained`
+    pub code: &'static str,
+    /// option or rule the entry belongs to, e.g. `ip-allow` or `rule 2`, or
+    /// `path:line` for an entry of a list file
+    pub option: String,
+    pub entry: String,
+    /// the other entry of a pair, empty if there is none
+    pub related: String,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, code: &'static str, option: &str, entry: &str) -> Self {
+        Finding {
+            severity,
+            code,
+            option: option.to_owned(),
+            entry: entry.to_owned(),
+            related: String::new(),
+            message: String::new(),
+        }
+    }
+
+    fn related(mut self, related: &str) -> Self {
+        self.related = related.to_owned();
+        self
+    }
+
+    fn message(mut self, message: String) -> Self {
+        self.message = message;
+        self
+    }
+}
+
+// an IP entry with the option or list file line it was given in
+struct IpEntry {
+    option: String,
+    value: String,
+    bounds: IpBounds,
+}
+
+// IPv4 entries wider than a /8 and IPv6 entries wider than a /16
+const BROAD_SPAN_IPV4: u128 = 1 << 24;
+const BROAD_SPAN_IPV6: u128 = 1 << 112;
+
+fn lint_entry(ip: &IpEntry) -> Vec<Finding> {
+    let (option, entry, bounds) = (ip.option.as_str(), ip.value.as_str(), &ip.bounds);
+    let mut findings = Vec::new();
+
+    if let Ok(net) = entry.parse::<IpNet>()
+        && net.addr() != net.network()
+    {
+        let msg = format!(
+            "'{}' has host bits set, the subnet is '{}'",
+            entry,
+            net.trunc()
+        );
+        findings.push(Finding::new(Severity::Warning, "host-bits", option, entry).message(msg));
+    }
+
+    if entry.contains('-')
+        && let Some(subnet) = bounds.to_subnet()
+    {
+        let msg = format!("'{}' can be written as '{}'", entry, subnet);
+        findings.push(Finding::new(Severity::Info, "range-is-subnet", option, entry).message(msg));
+    }
+
+    let broad = if bounds.is_ipv4 {
+        BROAD_SPAN_IPV4
+    } else {
+        BROAD_SPAN_IPV6
+    };
+
+    let limit = if bounds.is_ipv4 {
+        u32::MAX as u128
+    } else {
+        u128::MAX
+    };
+
+    if bounds.span() == limit {
+        let msg = format!("'{}' covers every address", entry);
+        findings.push(Finding::new(Severity::Error, "broad", option, entry).message(msg));
+    } else if bounds.span() >= broad {
+        let size = if bounds.is_ipv4 { "/8" } else { "/16" };
+        let msg = format!("'{}' covers more than a {}", entry, size);
+        findings.push(Finding::new(Severity::Warning, "broad", option, entry).message(msg));
+    }
+
+    findings
+}
+
+// entries sorted by their first address, IPv4 before IPv6; the sort is
+// stable, so equal entries stay in the order they were given
+fn sorted(entries: &[IpEntry]) -> Vec<&IpEntry> {
+    let mut sorted: Vec<_> = entries.iter().collect();
+
+    sorted.sort_by_key(|x| (!x.bounds.is_ipv4, x.bounds.lower, Reverse(x.bounds.upper)));
+    sorted
+}
+
+// one sweep over the sorted entries; of the entries starting before one, the
+// widest is the only candidate to contain or overlap it
+fn lint_pairs(entries: &[IpEntry]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    // first of a run of equal entries
+    let mut previous: Option<&IpEntry> = None;
+    let mut widest: Option<&IpEntry> = None;
+
+    for ip in sorted(entries) {
+        let (entry, bounds) = (&ip.value, &ip.bounds);
+
+        if widest.is_some_and(|x| x.bounds.is_ipv4 != bounds.is_ipv4) {
+            widest = None;
+        }
+
+        let finding = match (previous, widest) {
+            (Some(other), _) if other.bounds == *bounds => {
+                let msg = format!("'{}' duplicates '{}'", entry, other.value);
+                Some((Severity::Warning, "duplicate", other, msg))
+            }
+            (_, Some(other)) if other.bounds.contains(bounds) => {
+                let msg = format!("'{}' is contained in '{}'", entry, other.value);
+                Some((Severity::Warning, "contained", other, msg))
+            }
+            (_, Some(other)) if other.bounds.overlaps(bounds) => {
+                let msg = format!("'{}' overlaps '{}'", entry, other.value);
+                Some((Severity::Info, "overlap", other, msg))
+            }
+            _ => None,
+        };
+
+        if let Some((severity, code, other, msg)) = finding {
+            findings.push(
+                Finding::new(severity, code, &ip.option, entry)
+                    .related(&other.value)
+                    .message(msg),
+            );
+        }
+
+        if previous.is_none_or(|x| x.bounds != *bounds) {
+            previous = Some(ip);
+        }
+
+        if widest.is_none_or(|x| x.bounds.upper < bounds.upper) {
+            widest = Some(ip);
+        }
+    }
+
+    findings
+}
+
+// entries of `option` followed by those of its list files
+fn located(option: &str, values: &[String], files: &[PathBuf]) -> Result<Vec<(String, String)>> {
+    let mut located: Vec<(String, String)> = values
+        .iter()
+        .map(|x| (option.to_owned(), x.clone()))
+        .collect();
+
+    for path in files {
+        for (line, value) in list_file::read_list_file(path)? {
+            located.push((format!("{}:{}", path.display(), line), value));
+        }
+    }
+
+    Ok(located)
+}
+
+// only IP entries are linted; domains and keywords are skipped
+fn ip_entries(located: impl IntoIterator<Item = (String, String)>) -> Vec<IpEntry> {
+    located
+        .into_iter()
+        .filter_map(|(option, value)| {
+            let bounds = network::ip_bounds(&value).ok()?;
+            Some(IpEntry {
+                option,
+                value,
+                bounds,
+            })
+        })
+        .collect()
+}
+
+fn lint_ips(entries: &[IpEntry]) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = entries.iter().flat_map(lint_entry).collect();
+
+    findings.extend(lint_pairs(entries));
+    findings
+}
+
+fn bounds_to_string(bounds: IpBounds) -> String {
+    if let Some(subnet) = bounds.to_subnet() {
+        return subnet;
+    }
+
+    if bounds.is_ipv4 {
+        let lower = Ipv4Addr::from_bits(bounds.lower as u32);
+        let upper = Ipv4Addr::from_bits(bounds.upper as u32);
+        format!("{}-{}", lower, upper)
+    } else {
+        let lower = Ipv6Addr::from_bits(bounds.lower);
+        let upper = Ipv6Addr::from_bits(bounds.upper);
+        format!("{}-{}", lower, upper)
+    }
+}
+
+fn intervals(entries: &[&IpEntry], is_ipv4: bool) -> Intervals<u128> {
+    Intervals::new(
+        entries
+            .iter()
+            .filter(|x| x.bounds.is_ipv4 == is_ipv4)
+            .map(|x| (x.bounds.lower, x.bounds.upper))
+            .collect(),
+    )
+}
+
+// an entry that is never decided by its own list because the other list
+// wins for all of its addresses, found in the merged intervals of the other
+// list; a single entry covering it is named, otherwise the merged range
+fn lint_shadowed(precedence: Precedence, allow: &[IpEntry], deny: &[IpEntry]) -> Vec<Finding> {
+    let (entries, others, other_option) = match precedence {
+        Precedence::Deny => (allow, deny, "ip-deny"),
+        Precedence::Allow => (deny, allow, "ip-allow"),
+    };
+
+    let others = sorted(others);
+    let (ipv4, ipv6) = (intervals(&others, true), intervals(&others, false));
+
+    // the widest of the entries up to each position
+    let mut widest: Vec<&IpEntry> = Vec::with_capacity(others.len());
+
+    for &ip in &others {
+        let wider = widest
+            .last()
+            .copied()
+            .filter(|x| x.bounds.is_ipv4 == ip.bounds.is_ipv4 && x.bounds.upper >= ip.bounds.upper);
+        widest.push(wider.unwrap_or(ip));
+    }
+
+    let mut findings = Vec::new();
+
+    for ip in entries {
+        let bounds = &ip.bounds;
+        let merged = if bounds.is_ipv4 { &ipv4 } else { &ipv6 };
+
+        let Some((lower, upper)) = merged.covering(bounds.lower, bounds.upper) else {
+            continue;
+        };
+
+        let i = others.partition_point(|x| {
+            (!x.bounds.is_ipv4, x.bounds.lower) <= (!bounds.is_ipv4, bounds.lower)
+        });
+
+        let (other, option) = match widest[..i].last() {
+            Some(x) if x.bounds.contains(bounds) => (x.value.clone(), x.option.as_str()),
+            _ => {
+                let range = IpBounds {
+                    is_ipv4: bounds.is_ipv4,
+                    lower,
+                    upper,
+                };
+                (bounds_to_string(range), other_option)
+            }
+        };
+
+        let msg = format!(
+            "'{}' in {} is shadowed by '{}' in {}",
+            ip.value, ip.option, other, option
+        );
+        findings.push(
+            Finding::new(Severity::Warning, "shadowed", &ip.option, &ip.value)
+                .related(&other)
+                .message(msg),
+        );
+    }
+
+    findings
+}
+
+// the fields of a rule as written; a field containing ALL is left out, as it
+// matches anything just like a missing one
+fn rule_fields(rule: &str) -> HashMap<&str, Vec<String>> {
+    rule.split_whitespace()
+        .skip(1)
+        .filter_map(|x| x.split_once('='))
+        .map(|(key, value)| {
+            let values = value
+                .split(',')
+                .filter(|x| !x.is_empty())
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            (key, values)
+        })
+        .filter(|(_, values)| !values.iter().any(|x| x == "ALL"))
+        .collect()
+}
+
+fn covers_value(key: &str, values: &[String], value: &str) -> bool {
+    if values.iter().any(|x| x == value) {
+        return true;
+    }
+
+    if key != "hosts" {
+        return false;
+    }
+
+    let Ok(bounds) = network::ip_bounds(value) else {
+        return false;
+    };
+
+    values
+        .iter()
+        .filter_map(|x| network::ip_bounds(x).ok())
+        .any(|x| x.contains(&bounds))
+}
+
+// true if every login `later` matches is matched by `earlier` as well; this
+// errs on the side of not reporting, e.g. groups are never expanded
+fn covers(earlier: &HashMap<&str, Vec<String>>, later: &HashMap<&str, Vec<String>>) -> bool {
+    earlier.iter().all(|(key, values)| {
+        later
+            .get(key)
+            .is_some_and(|x| x.iter().all(|value| covers_value(key, values, value)))
+    })
+}
+
+fn lint_rules(rules: &[String]) -> Vec<Finding> {
+    let fields: Vec<_> = rules.iter().map(|x| rule_fields(x)).collect();
+    let mut findings = Vec::new();
+
+    for (j, rule) in rules.iter().enumerate() {
+        let option = format!("rule {}", j + 1);
+
+        if let Some(hosts) = fields[j].get("hosts") {
+            let entries = ip_entries(hosts.iter().map(|x| (option.clone(), x.clone())));
+            findings.extend(lint_ips(&entries));
+        }
+
+        let Some(i) = (0..j).find(|&i| covers(&fields[i], &fields[j])) else {
+            continue;
+        };
+
+        let related = format!("rule {}", i + 1);
+        let msg = format!(
+            "rule {} can never match, {} matches all of its logins first",
+            j + 1,
+            related
+        );
+        let severity = if rule.starts_with("deny") {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+
+        findings.push(
+            Finding::new(severity, "unreachable-rule", &option, rule)
+                .related(&related)
+                .message(msg),
+        );
+    }
+
+    findings
+}
+
+/// Lints the IP lists, including their list files, and the rules of `cli`,
+/// most severe findings first.
+pub fn lint(cli: &Cli) -> Result<Vec<Finding>> {
+    let allow = ip_entries(located("ip-allow", &cli.ip_allow, &cli.ip_allow_file)?);
+    let deny = ip_entries(located("ip-deny", &cli.ip_deny, &cli.ip_deny_file)?);
+
+    let mut findings = lint_ips(&allow);
+
+    findings.extend(lint_ips(&deny));
+    findings.extend(lint_shadowed(cli.precedence, &allow, &deny));
+    findings.extend(lint_rules(&cli.rule));
+
+    findings.sort_by_key(|x| Reverse(x.severity));
+    Ok(findings)
+}
+
+fn check_args(module_args: Vec<String>) -> Result<Vec<Finding>> {
+    let mut args = vec![config::PAM_MODULE_LIB.to_owned()];
+    args.extend(module_args);
+
+    // entries the module would reject are reported as an error instead
+    policy::policy_from_cli(parser::process_args(args.clone())?)?;
+
+    lint(&parser::process_args(args)?)
+}
+
+fn format_finding(finding: &Finding, format: LogFormat) -> String {
+    let fields = [
+        ("severity", finding.severity.as_str()),
+        ("code", finding.code),
+        ("option", finding.option.as_str()),
+        ("entry", finding.entry.as_str()),
+        ("related", finding.related.as_str()),
+        ("message", finding.message.as_str()),
+    ];
+
+    match format {
+        LogFormat::Text => format!(
+            "{}: {}: {} [{}]",
+            finding.severity.as_str(),
+            finding.option,
+            finding.message,
+            finding.code
+        ),
+        LogFormat::Kv => log::format_kv(&fields),
+        LogFormat::Json => log::format_json(&fields),
+    }
+}
+
+pub fn main() -> ExitCode {
+    let cli = LintCli::parse();
+
+    let findings = match check_args(cli.module_args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    for finding in &findings {
+        println!("{}", format_finding(finding, cli.format));
+    }
+
+    if findings.iter().any(|x| x.severity >= Severity::Warning) {
+        return ExitCode::from(1);
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(args: &[&str]) -> Result<Vec<(&'static str, String)>> {
+        let args = args.iter().map(|x| x.to_string()).collect();
+
+        Ok(check_args(args)?
+            .into_iter()
+            .map(|x| (x.code, x.entry))
+            .collect())
+    }
+
+    #[test]
+    fn test_lint_tp_clean() -> Result<()> {
+        let ret = codes(&["--ip-allow=10.1.0.0/16,192.0.2.1", "--ip-deny=10.2.0.0/16"])?;
+
+        assert!(ret.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tp_duplicate_and_contained() -> Result<()> {
+        let ret = codes(&["--ip-allow=10.0.0.0/16,10.0.1.0/24,10.0.0.0-10.0.255.255"])?;
+
+        assert!(ret.contains(&("contained", "10.0.1.0/24".to_owned())));
+        assert!(ret.contains(&("duplicate", "10.0.0.0-10.0.255.255".to_owned())));
+        assert!(ret.contains(&("range-is-subnet", "10.0.0.0-10.0.255.255".to_owned())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tp_overlap() -> Result<()> {
+        let ret = codes(&["--ip-deny=192.0.2.0-192.0.2.100,192.0.2.64/26"])?;
+
+        assert_eq!(ret, vec![("overlap", "192.0.2.64/26".to_owned())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tp_host_bits_and_broad() -> Result<()> {
+        let args = ["--ip-allow=10.0.0.1/8,0.0.0.0/0,::/8"];
+        let findings = check_args(args.iter().map(|x| x.to_string()).collect())?;
+
+        // most severe first
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[0].entry, "0.0.0.0/0");
+
+        let ret: Vec<_> = findings
+            .iter()
+            .map(|x| (x.code, x.entry.as_str()))
+            .collect();
+
+        assert!(ret.contains(&("host-bits", "10.0.0.1/8")));
+        assert!(ret.contains(&("broad", "::/8")));
+        assert!(!ret.contains(&("broad", "10.0.0.1/8")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tp_shadowed() -> Result<()> {
+        let args = ["--ip-allow=10.1.0.0/16", "--ip-deny=10.0.0.0/8"];
+
+        assert_eq!(codes(&args)?, vec![("shadowed", "10.1.0.0/16".to_owned())]);
+
+        let args = [
+            "--ip-allow=10.0.0.0/8",
+            "--ip-deny=10.1.0.0/16",
+            "--precedence=allow",
+        ];
+
+        assert_eq!(codes(&args)?, vec![("shadowed", "10.1.0.0/16".to_owned())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tp_shadowed_by_merged_entries() -> Result<()> {
+        let args = [
+            "--ip-allow=10.0.1.0/24,10.0.2.0/24",
+            "--ip-deny=10.0.0.5-10.0.1.127,10.0.1.128/25,10.0.2.1-10.0.2.9",
+        ];
+        let findings = check_args(args.map(str::to_owned).to_vec())?;
+
+        let ret: Vec<_> = findings
+            .iter()
+            .filter(|x| x.code == "shadowed")
+            .map(|x| (x.entry.as_str(), x.related.as_str()))
+            .collect();
+
+        assert_eq!(ret, vec![("10.0.1.0/24", "10.0.0.5-10.0.1.255")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_pairs_tp_large_list() -> Result<()> {
+        // 50k distinct /32 entries, every 1000th repeated and one /16 on top
+        let mut values: Vec<String> = (0..50_000u32)
+            .map(|x| Ipv4Addr::from_bits(0x0a00_0000 + x * 3).to_string())
+            .collect();
+
+        let repeated: Vec<_> = values.iter().step_by(1000).cloned().collect();
+
+        values.extend(repeated);
+        values.push("10.0.0.0/16".to_owned());
+
+        let entries = ip_entries(values.into_iter().map(|x| ("ip-allow".to_owned(), x)));
+        let findings = lint_pairs(&entries);
+
+        let count = |code| findings.iter().filter(|x| x.code == code).count();
+
+        assert_eq!(count("duplicate"), 50);
+        // the /16 holds 10.0.0.0 up to 10.0.255.255, i.e. entries 0..21846
+        assert_eq!(count("contained"), 21846);
+        assert_eq!(count("overlap"), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tp_unreachable_rule() -> Result<()> {
+        let findings = check_args(vec![
+            "--rule=permit users=root hosts=10.0.0.0/8".to_owned(),
+            "--rule=deny users=root hosts=10.1.0.0/16 services=sshd".to_owned(),
+            "--rule=deny users=root,alice".to_owned(),
+            "--rule=permit users=ALL".to_owned(),
+            "--rule=deny".to_owned(),
+        ])?;
+
+        let ret: Vec<_> = findings
+            .iter()
+            .map(|x| (x.option.as_str(), x.related.as_str()))
+            .collect();
+
+        assert_eq!(ret, vec![("rule 2", "rule 1"), ("rule 5", "rule 4")]);
+        assert_eq!(findings[0].severity, Severity::Error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tp_list_file() -> Result<()> {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/lists/ip_allow.list");
+        let findings = check_args(vec![
+            format!("--ip-allow-file={}", path.display()),
+            "--ip-deny=10.2.0.0/16".to_owned(),
+        ])?;
+
+        let ret: Vec<_> = findings
+            .iter()
+            .map(|x| (x.code, x.option.clone(), x.entry.as_str()))
+            .collect();
+
+        assert_eq!(
+            ret,
+            vec![("shadowed", format!("{}:3", path.display()), "10.2.0.0/16")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lint_tn_invalid_entry() -> Result<()> {
+        let ret = codes(&["--ip-allow=10.0.0.0/33"]).expect_err("must fail");
+
+        assert!(ret.to_string().contains("10.0.0.0/33"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_finding_tp_kv() -> Result<()> {
+        let finding = Finding::new(Severity::Warning, "duplicate", "ip-allow", "10.0.0.1")
+            .related("10.0.0.1/32")
+            .message("'10.0.0.1' duplicates '10.0.0.1/32'".to_owned());
+
+        assert_eq!(
+            format_finding(&finding, LogFormat::Kv),
+            "severity=\"warning\" code=\"duplicate\" option=\"ip-allow\" entry=\"10.0.0.1\" \
+             related=\"10.0.0.1/32\" message=\"'10.0.0.1' duplicates '10.0.0.1/32'\""
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+/// Reads a list file with one entry per line. `#` starts a comment, and
+/// blank lines are skipped. Entries come with their 1-based line number.
+pub fn read_list_file(path: &Path) -> Result<Vec<(usize, String)>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("cannot read list file '{}'", path.display()))?;
+
+    let entries = content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let entry = line.split('#').next().unwrap_or("").trim();
+            (!entry.is_empty()).then(|| (i + 1, entry.to_owned()))
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Builds a filter from the list `entries` and the entries of `files` with
+/// the same `build` function as the list alone. On an error in a file, the
+/// offending entry is looked up again so that its line can be named.
+pub fn build_with_files<T>(
+    entries: Vec<String>,
+    files: &[PathBuf],
+    build: impl Fn(Vec<String>) -> Result<T>,
+) -> Result<T> {
+    let mut lines = Vec::new();
+
+    for path in files {
+        for (line, entry) in read_list_file(path)? {
+            lines.push((path, line, entry));
+        }
+    }
+
+    if lines.is_empty() {
+        return build(entries);
+    }
+
+    let mut all = entries.clone();
+    all.extend(lines.iter().map(|(_, _, entry)| entry.clone()));
+
+    let err = match build(all) {
+        Ok(x) => return Ok(x),
+        Err(e) => e,
+    };
+
+    // errors in the module arguments are reported as they are
+    build(entries)?;
+
+    for (path, line, entry) in &lines {
+        if let Err(e) = build(vec![entry.clone()]) {
+            bail!("{}:{}: {}", path.display(), line, e);
+        }
+    }
+
+    Err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::{self, Filter};
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/lists")
+            .join(name)
+    }
+
+    #[test]
+    fn test_read_list_file_tp_comments() -> Result<()> {
+        let entries = read_list_file(&fixture("ip_allow.list"))?;
+
+        assert_eq!(
+            entries,
+            vec![
+                (2, "10.1.0.0/16".to_owned()),
+                (3, "10.2.0.0/16".to_owned()),
+                (5, "192.0.2.10-192.0.2.20".to_owned()),
+                (6, "2001:db8::/32".to_owned()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_list_file_tn_not_found() -> Result<()> {
+        let ret = read_list_file(Path::new("/nonexistent.list")).expect_err("must fail");
+
+        assert_eq!(ret.to_string(), "cannot read list file '/nonexistent.list'");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_files_tp_ips() -> Result<()> {
+        let filter = build_with_files(
+            vec!["198.51.100.1".to_owned()],
+            &[fixture("ip_allow.list")],
+            filter::filter_from_ips,
+        )?;
+
+        assert!(filter.contains(&"198.51.100.1".parse()?));
+        assert!(filter.contains(&"10.2.3.4".parse()?));
+        assert!(filter.contains(&"192.0.2.15".parse()?));
+        assert!(filter.contains(&"2001:db8::1".parse()?));
+        assert!(!filter.contains(&"10.3.0.1".parse()?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_files_tp_users_and_domains() -> Result<()> {
+        let users = build_with_files(vec![], &[fixture("users.list")], filter::filter_from_users)?;
+        let domains = build_with_files(
+            vec![],
+            &[fixture("domains.list")],
+            filter::filter_from_domains,
+        )?;
+
+        assert!(users.contains("backup-agent"));
+        assert!(!users.contains("guest"));
+        assert!(domains.contains("corp.example"));
+        assert!(domains.contains("runner1.ci.corp.example"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_files_tn_line_number() -> Result<()> {
+        let files = [fixture("ip_bad.list")];
+        let ret = build_with_files(vec![], &files, filter::filter_from_ips).expect_err("must fail");
+
+        assert!(
+            ret.to_string()
+                .starts_with(&format!("{}:3: ", files[0].display()))
+        );
+        assert!(ret.to_string().contains("10.0.0.0/33"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_files_tn_module_argument() -> Result<()> {
+        let ret = build_with_files(
+            vec!["bad user".to_owned()],
+            &[fixture("users.list")],
+            filter::filter_from_users,
+        )
+        .expect_err("must fail");
+
+        assert_eq!(ret.to_string(), "'bad user' wrong username syntax");
+
+        Ok(())
+    }
+}
@@ -1,12 +1,20 @@
 use std::ffi::{c_char, c_int};
+use std::fmt::Write;
 
 use libc;
 
 use crate::ffi::pam;
+use crate::parser::LogFormat;
 
+// messages are passed as an argument of "%s", never as the format itself, so
+// a '%' in a user or host name cannot be read as a conversion
 pub fn syslog(priority: c_int, msg: &str) {
     unsafe {
-        libc::syslog(priority, format!("{}\0", msg).as_ptr() as *const c_char);
+        libc::syslog(
+            priority,
+            c"%s".as_ptr(),
+            format!("{}\0", msg).as_ptr() as *const c_char,
+        );
     }
 }
 
@@ -15,11 +23,47 @@ pub fn pam_syslog(pamh: *const pam::pam_handle_t, priority: c_int, msg: &str) {
         pam::pam_syslog(
             pamh,
             priority,
+            c"%s".as_ptr(),
             format!("{}\0", msg).as_ptr() as *const c_char,
         );
     }
 }
 
+/// Receives the messages of an evaluation, so that it can also run without a
+/// PAM handle, e.g. in `pam-network-filter-check`.
+pub trait Logger {
+    fn log(&self, priority: c_int, msg: &str);
+}
+
+/// Logs through `pam_syslog` of the calling application.
+pub struct PamLogger(pub *const pam::pam_handle_t);
+
+impl Logger for PamLogger {
+    fn log(&self, priority: c_int, msg: &str) {
+        pam_syslog(self.0, priority, msg);
+    }
+}
+
+/// Passes every message to `inner` as a single `msg` field of `format`, so
+/// that the free text of the checks, which names users and hosts, cannot add
+/// fields to a structured log.
+pub struct StructuredLogger<'a> {
+    pub inner: &'a dyn Logger,
+    pub format: LogFormat,
+}
+
+impl Logger for StructuredLogger<'_> {
+    fn log(&self, priority: c_int, msg: &str) {
+        let fields = [("msg", msg)];
+
+        match self.format {
+            LogFormat::Text => self.inner.log(priority, msg),
+            LogFormat::Kv => self.inner.log(priority, &format_kv(&fields)),
+            LogFormat::Json => self.inner.log(priority, &format_json(&fields)),
+        }
+    }
+}
+
 pub fn log_unimplemented_pam_function(pamh: *mut pam::pam_handle_t, name: &str) {
     pam_syslog(
         pamh,
@@ -27,3 +71,89 @@ pub fn log_unimplemented_pam_function(pamh: *mut pam::pam_handle_t, name: &str)
         format!("feature '{}' not implemented", name).as_str(),
     );
 }
+
+// JSON string escaping, which also keeps a quoted key=value pair from being
+// closed early or split across lines
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// `key="value"` pairs separated by spaces, e.g. `decision="deny" user="root"`.
+/// Every value is quoted and escaped, so it cannot add fields of its own.
+pub fn format_kv(fields: &[(&str, &str)]) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// One JSON object per line with the fields in the given order.
+pub fn format_json(fields: &[(&str, &str)]) -> String {
+    let members = fields
+        .iter()
+        .map(|(key, value)| format!("\"{}\":\"{}\"", key, escape(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{{{}}}", members)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_format_kv_tp() -> Result<()> {
+        let line = format_kv(&[("decision", "deny"), ("user", "root"), ("tty", "")]);
+
+        assert_eq!(line, r#"decision="deny" user="root" tty="""#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_kv_tn_forged_field() -> Result<()> {
+        let line = format_kv(&[("user", "x\" decision=\"allow"), ("rhost", "a\nb")]);
+
+        assert_eq!(line, r#"user="x\" decision=\"allow" rhost="a\nb""#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_json_tp() -> Result<()> {
+        let line = format_json(&[("decision", "allow"), ("phase", "account")]);
+
+        assert_eq!(line, r#"{"decision":"allow","phase":"account"}"#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_json_tn_control_and_backslash() -> Result<()> {
+        let line = format_json(&[("user", "a\\b\u{1b}[31m%n")]);
+
+        assert_eq!(line, r#"{"user":"a\\b\u001b[31m%n"}"#);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+use std::ffi::{c_int, c_void};
+use std::fs;
+use std::io::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use libc;
+
+use crate::pattern;
+
+pub const PROC_NET_ARP: &str = "/proc/net/arp";
+
+pub type MacAddr = [u8; 6];
+
+// struct ndmsg from linux/neighbour.h, not exported by libc
+#[repr(C)]
+#[derive(Default)]
+struct Ndmsg {
+    ndm_family: u8,
+    ndm_pad1: u8,
+    ndm_pad2: u16,
+    ndm_ifindex: i32,
+    ndm_state: u16,
+    ndm_flags: u8,
+    ndm_type: u8,
+}
+
+#[repr(C)]
+struct NeighDumpRequest {
+    header: libc::nlmsghdr,
+    msg: Ndmsg,
+}
+
+const NLMSG_HDRLEN: usize = nlmsg_align(size_of::<libc::nlmsghdr>());
+const RTA_HDRLEN: usize = 4;
+
+const fn nlmsg_align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+pub fn parse_mac(mac: &str) -> Result<MacAddr> {
+    if !pattern::pat_mac().is_match(mac)? {
+        bail!("'{}' wrong MAC address syntax", mac);
+    }
+
+    let mut addr = MacAddr::default();
+
+    for (octet, hex) in addr.iter_mut().zip(mac.split(':')) {
+        *octet = u8::from_str_radix(hex, 16)?;
+    }
+
+    Ok(addr)
+}
+
+pub fn format_mac(mac: &MacAddr) -> String {
+    mac.iter()
+        .map(|octet| format!("{:02x}", octet))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// /proc/net/arp layout:
+// IP address       HW type     Flags       HW address            Mask     Device
+// 192.168.1.1      0x1         0x2         aa:bb:cc:dd:ee:ff     *        eth0
+//
+// incomplete entries (flags 0x0) carry an all-zero address and are skipped
+fn parse_arp_table(content: &str) -> Vec<(IpAddr, MacAddr)> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+
+            if let [ip, _, flags, mac, ..] = fields[..] {
+                let ip = ip.parse::<IpAddr>().ok()?;
+                let mac = parse_mac(mac).ok()?;
+
+                if flags == "0x0" || mac == MacAddr::default() {
+                    return None;
+                }
+
+                Some((ip, mac))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn lookup_file(path: &Path, ip: IpAddr) -> Result<Option<MacAddr>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("cannot read neighbour table '{}'", path.display()))?;
+
+    Ok(parse_arp_table(&content)
+        .into_iter()
+        .find(|(addr, _)| *addr == ip)
+        .map(|(_, mac)| mac))
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+// parses one datagram of an RTM_GETNEIGH dump into `entries`
+// returns true once NLMSG_DONE is reached
+fn parse_neigh_dump(buf: &[u8], entries: &mut Vec<(IpAddr, MacAddr)>) -> Result<bool> {
+    let mut offset = 0;
+
+    while offset + NLMSG_HDRLEN <= buf.len() {
+        let msg_len = read_u32(buf, offset) as usize;
+        let msg_type = read_u16(buf, offset + 4);
+
+        if msg_len < NLMSG_HDRLEN || offset + msg_len > buf.len() {
+            bail!("malformed netlink message");
+        }
+
+        if c_int::from(msg_type) == libc::NLMSG_DONE {
+            return Ok(true);
+        }
+
+        if c_int::from(msg_type) == libc::NLMSG_ERROR {
+            let errno = read_u32(buf, offset + NLMSG_HDRLEN) as i32;
+            bail!(
+                "netlink neighbour dump failed: {}",
+                Error::from_raw_os_error(-errno)
+            );
+        }
+
+        if msg_type == libc::RTM_NEWNEIGH {
+            let msg = &buf[offset + NLMSG_HDRLEN..offset + msg_len];
+
+            if let Some(entry) = parse_neigh_msg(msg) {
+                entries.push(entry);
+            }
+        }
+
+        offset += nlmsg_align(msg_len);
+    }
+
+    Ok(false)
+}
+
+fn parse_neigh_msg(msg: &[u8]) -> Option<(IpAddr, MacAddr)> {
+    let ndmsg_len = nlmsg_align(size_of::<Ndmsg>());
+
+    if msg.len() < ndmsg_len {
+        return None;
+    }
+
+    let state = read_u16(msg, 8);
+
+    if state & (libc::NUD_INCOMPLETE | libc::NUD_FAILED) != 0 {
+        return None;
+    }
+
+    let mut offset = ndmsg_len;
+    let mut dst = None;
+    let mut lladdr = None;
+
+    while offset + RTA_HDRLEN <= msg.len() {
+        let rta_len = read_u16(msg, offset) as usize;
+        let rta_type = read_u16(msg, offset + 2);
+
+        if rta_len < RTA_HDRLEN || offset + rta_len > msg.len() {
+            break;
+        }
+
+        let data = &msg[offset + RTA_HDRLEN..offset + rta_len];
+
+        match (rta_type, data.len()) {
+            (libc::NDA_DST, 4) => {
+                dst = Some(IpAddr::V4(Ipv4Addr::from(
+                    <[u8; 4]>::try_from(data).unwrap(),
+                )));
+            }
+            (libc::NDA_DST, 16) => {
+                dst = Some(IpAddr::V6(Ipv6Addr::from(
+                    <[u8; 16]>::try_from(data).unwrap(),
+                )));
+            }
+            (libc::NDA_LLADDR, 6) => {
+                lladdr = Some(MacAddr::try_from(data).unwrap());
+            }
+            _ => {}
+        }
+
+        offset += nlmsg_align(rta_len);
+    }
+
+    Some((dst?, lladdr?))
+}
+
+fn lookup_netlink(ip: Ipv6Addr) -> Result<Option<MacAddr>> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+
+    if fd < 0 {
+        bail!("cannot open netlink socket: {}", Error::last_os_error());
+    }
+
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let request = NeighDumpRequest {
+        header: libc::nlmsghdr {
+            nlmsg_len: size_of::<NeighDumpRequest>() as u32,
+            nlmsg_type: libc::RTM_GETNEIGH,
+            nlmsg_flags: (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16,
+            nlmsg_seq: 1,
+            nlmsg_pid: 0,
+        },
+        msg: Ndmsg {
+            ndm_family: libc::AF_INET6 as u8,
+            ..Default::default()
+        },
+    };
+
+    let ret = unsafe {
+        libc::send(
+            fd.as_raw_fd(),
+            &request as *const NeighDumpRequest as *const c_void,
+            size_of::<NeighDumpRequest>(),
+            0,
+        )
+    };
+
+    if ret < 0 {
+        bail!("cannot send netlink request: {}", Error::last_os_error());
+    }
+
+    let mut buf = vec![0u8; 32 * 1024];
+    let mut entries = Vec::new();
+
+    loop {
+        let len = unsafe {
+            libc::recv(
+                fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+                0,
+            )
+        };
+
+        if len < 0 {
+            bail!(
+                "cannot receive netlink response: {}",
+                Error::last_os_error()
+            );
+        }
+
+        if len == 0 || parse_neigh_dump(&buf[..len as usize], &mut entries)? {
+            break;
+        }
+    }
+
+    Ok(entries
+        .into_iter()
+        .find(|(addr, _)| *addr == IpAddr::V6(ip))
+        .map(|(_, mac)| mac))
+}
+
+/// Looks up the hardware address of `ip` in the neighbour table.
+///
+/// Without `table`, IPv4 addresses are looked up in `/proc/net/arp` and IPv6
+/// addresses through a netlink neighbour dump. With `table`, the given file in
+/// `/proc/net/arp` layout is used for both address families instead.
+pub fn lookup_mac(ip: IpAddr, table: Option<&Path>) -> Result<Option<MacAddr>> {
+    match (table, ip) {
+        (Some(path), _) => lookup_file(path, ip),
+        (None, IpAddr::V4(_)) => lookup_file(Path::new(PROC_NET_ARP), ip),
+        (None, IpAddr::V6(ipv6)) => lookup_netlink(ipv6),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/neigh/arp")
+    }
+
+    #[test]
+    fn test_parse_mac_tp() -> Result<()> {
+        let ret = parse_mac("AA:bb:0c:dd:ee:0F");
+
+        assert_eq!(ret?, [0xaa, 0xbb, 0x0c, 0xdd, 0xee, 0x0f]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mac_tn_syntax() -> Result<()> {
+        for mac in ["aa:bb:cc:dd:ee", "aa-bb-cc-dd-ee-ff", "aa:bb:cc:dd:ee:fg"] {
+            let ret = parse_mac(mac).expect_err("must fail");
+
+            assert!(ret.to_string().contains("wrong MAC address syntax"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_mac_tp_fixture() -> Result<()> {
+        let ret = lookup_mac("192.168.1.1".parse()?, Some(&fixture()));
+
+        assert_eq!(ret?, Some(parse_mac("aa:bb:cc:dd:ee:ff")?));
+
+        let ret = lookup_mac("fe80::1".parse()?, Some(&fixture()));
+
+        assert_eq!(ret?, Some(parse_mac("02:00:00:00:00:01")?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_mac_tn_fixture_incomplete() -> Result<()> {
+        let ret = lookup_mac("192.168.1.3".parse()?, Some(&fixture()));
+
+        assert_eq!(ret?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_mac_tn_fixture_missing() -> Result<()> {
+        let ret = lookup_mac("10.9.9.9".parse()?, Some(&fixture()));
+
+        assert_eq!(ret?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_mac_tn_table_not_found() -> Result<()> {
+        let ret = lookup_mac("10.9.9.9".parse()?, Some(Path::new("/nonexistent")))
+            .expect_err("must fail");
+
+        assert!(ret.to_string().contains("cannot read neighbour table"));
+
+        Ok(())
+    }
+
+    fn push_attr(buf: &mut Vec<u8>, rta_type: u16, data: &[u8]) {
+        buf.extend_from_slice(&((RTA_HDRLEN + data.len()) as u16).to_ne_bytes());
+        buf.extend_from_slice(&rta_type.to_ne_bytes());
+        buf.extend_from_slice(data);
+        buf.resize(nlmsg_align(buf.len()), 0);
+    }
+
+    fn neigh_msg(state: u16, dst: &[u8], lladdr: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0u8; size_of::<Ndmsg>()];
+        payload[0] = libc::AF_INET6 as u8;
+        payload[8..10].copy_from_slice(&state.to_ne_bytes());
+        push_attr(&mut payload, libc::NDA_DST, dst);
+        push_attr(&mut payload, libc::NDA_LLADDR, lladdr);
+
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        msg.extend_from_slice(&libc::RTM_NEWNEIGH.to_ne_bytes());
+        msg.resize(NLMSG_HDRLEN, 0);
+        msg.extend_from_slice(&payload);
+        msg
+    }
+
+    #[test]
+    fn test_parse_neigh_dump_tp() -> Result<()> {
+        let ip: Ipv6Addr = "fe80::1".parse()?;
+        let mac = [2, 0, 0, 0, 0, 1];
+
+        let mut buf = neigh_msg(libc::NUD_REACHABLE, &ip.octets(), &mac);
+        buf.extend(neigh_msg(
+            libc::NUD_FAILED,
+            &Ipv6Addr::LOCALHOST.octets(),
+            &mac,
+        ));
+
+        let mut entries = Vec::new();
+        let done = parse_neigh_dump(&buf, &mut entries)?;
+
+        assert!(!done);
+        assert_eq!(entries, vec![(IpAddr::V6(ip), mac)]);
+
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
 
 use anyhow::{Result, bail};
-use ipnet::Ipv4Net;
-use roaring::RoaringBitmap;
+use ipnet::{Ipv4Net, Ipv6Net};
 
 use crate::pattern;
 
@@ -17,18 +17,112 @@ enum Pattern {
     Ipv6Range,
 }
 
-#[allow(dead_code)]
 #[derive(Debug)]
-pub enum IpList {
-    V4(Ipv4List),
+pub struct Ipv4List {
+    pub intervals: Intervals<u32>,
 }
 
-#[allow(dead_code)]
 #[derive(Debug)]
-pub struct Ipv4List {
-    pub ips: RoaringBitmap,
-    pub subnets: Vec<Ipv4Net>,
-    pub ranges: Vec<(Ipv4Addr, Ipv4Addr)>,
+pub struct Ipv6List {
+    pub intervals: Intervals<u128>,
+}
+
+pub trait Address: Copy + Ord {
+    fn checked_next(self) -> Option<Self>;
+}
+
+impl Address for u32 {
+    fn checked_next(self) -> Option<Self> {
+        self.checked_add(1)
+    }
+}
+
+impl Address for u128 {
+    fn checked_next(self) -> Option<Self> {
+        self.checked_add(1)
+    }
+}
+
+/// Addresses of all entries of a list folded into sorted intervals that
+/// neither overlap nor touch, so a lookup is a binary search in O(log n).
+#[derive(Debug)]
+pub struct Intervals<T> {
+    bounds: Vec<(T, T)>,
+}
+
+impl<T: Address> Intervals<T> {
+    /// Merges inclusive `(lower, upper)` bounds given in any order.
+    pub fn new(mut bounds: Vec<(T, T)>) -> Self {
+        bounds.sort_unstable();
+
+        let mut merged: Vec<(T, T)> = Vec::with_capacity(bounds.len());
+
+        for (lower, upper) in bounds {
+            match merged.last_mut() {
+                // overlapping or adjacent; nothing follows the last address
+                Some(last) if last.1.checked_next().is_none_or(|x| lower <= x) => {
+                    last.1 = last.1.max(upper);
+                }
+                _ => merged.push((lower, upper)),
+            }
+        }
+
+        merged.shrink_to_fit();
+
+        Intervals { bounds: merged }
+    }
+
+    pub fn contains(&self, ip: T) -> bool {
+        self.contains_by(ip, |lower, ip| lower <= ip)
+    }
+
+    // `le` compares the lower bound of an interval with `ip`, so that tests
+    // can count the comparisons of a lookup
+    fn contains_by(&self, ip: T, mut le: impl FnMut(&T, &T) -> bool) -> bool {
+        // the last interval starting at or before `ip` is the only candidate
+        let i = self.bounds.partition_point(|(lower, _)| le(lower, &ip));
+
+        i > 0 && ip <= self.bounds[i - 1].1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    /// The interval holding all of `lower..=upper`. As intervals do not
+    /// touch, addresses covered by the list always lie in a single one.
+    pub fn covering(&self, lower: T, upper: T) -> Option<(T, T)> {
+        let i = self.bounds.partition_point(|(x, _)| *x <= lower);
+        let bounds = self.bounds[..i].last()?;
+
+        (upper <= bounds.1).then_some(*bounds)
+    }
+}
+
+impl Pattern {
+    fn is_ipv4(&self) -> bool {
+        matches!(self, Self::Ipv4Addr | Self::Ipv4Net | Self::Ipv4Range)
+    }
+}
+
+impl Ipv4List {
+    pub fn contains(&self, ip: &Ipv4Addr) -> bool {
+        self.intervals.contains(ip.to_bits())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+}
+
+impl Ipv6List {
+    pub fn contains(&self, ip: &Ipv6Addr) -> bool {
+        self.intervals.contains(ip.to_bits())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
 }
 
 fn find_ip_match(ip: &str) -> Result<Pattern> {
@@ -50,6 +144,24 @@ fn find_ip_match(ip: &str) -> Result<Pattern> {
         return Ok(Pattern::Ipv4Addr);
     }
 
+    let pat_ipv6_range = pattern::pat_ipv6_range();
+
+    if pat_ipv6_range.is_match(ip)? {
+        return Ok(Pattern::Ipv6Range);
+    }
+
+    let pat_ipv6_subnet = pattern::pat_ipv6_subnet();
+
+    if pat_ipv6_subnet.is_match(ip)? {
+        return Ok(Pattern::Ipv6Net);
+    }
+
+    let pat_ipv6 = pattern::pat_ipv6();
+
+    if pat_ipv6.is_match(ip)? {
+        return Ok(Pattern::Ipv6Addr);
+    }
+
     bail!("'{}' no matching pattern", ip)
 }
 
@@ -64,38 +176,152 @@ fn is_domain(domain: &str) -> Result<()> {
     bail!("'{}' wrong domain syntax", domain)
 }
 
+fn parse_ip_range<T>(ip: &str) -> Result<(T, T)>
+where
+    T: FromStr + Ord,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let range: Vec<_> = ip.split('-').collect();
+
+    if let [lower, upper] = &range[0..2] {
+        let lower = lower.parse::<T>()?;
+        let upper = upper.parse::<T>()?;
+
+        if lower >= upper {
+            bail!(
+                "'{}' IP on left side should be lower than the right one",
+                ip
+            );
+        }
+
+        Ok((lower, upper))
+    } else {
+        bail!("'{}' wrong input for IP range syntax", ip);
+    }
+}
+
+/// Addresses covered by an IP list entry as inclusive bounds. IPv4 and IPv6
+/// entries are never compared with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpBounds {
+    pub is_ipv4: bool,
+    pub lower: u128,
+    pub upper: u128,
+}
+
+impl IpBounds {
+    pub fn contains(&self, other: &IpBounds) -> bool {
+        self.is_ipv4 == other.is_ipv4 && self.lower <= other.lower && other.upper <= self.upper
+    }
+
+    pub fn overlaps(&self, other: &IpBounds) -> bool {
+        self.is_ipv4 == other.is_ipv4 && self.lower <= other.upper && other.lower <= self.upper
+    }
+
+    // number of addresses minus one, so that ::/0 does not overflow
+    pub fn span(&self) -> u128 {
+        self.upper - self.lower
+    }
+
+    /// The subnet with exactly these bounds, if there is one.
+    pub fn to_subnet(self) -> Option<String> {
+        let span = self.span();
+
+        if span & span.wrapping_add(1) != 0 || self.lower & span != 0 {
+            return None;
+        }
+
+        let subnet = if self.is_ipv4 {
+            let len = 32 - span.count_ones() as u8;
+            Ipv4Net::new(Ipv4Addr::from_bits(self.lower as u32), len)
+                .ok()?
+                .to_string()
+        } else {
+            let len = 128 - span.count_ones() as u8;
+            Ipv6Net::new(Ipv6Addr::from_bits(self.lower), len)
+                .ok()?
+                .to_string()
+        };
+
+        Some(subnet)
+    }
+}
+
+pub fn ip_bounds(ip: &str) -> Result<IpBounds> {
+    let (is_ipv4, lower, upper) = match find_ip_match(ip)? {
+        Pattern::Ipv4Addr => {
+            let ip = ip.parse::<Ipv4Addr>()?.to_bits().into();
+            (true, ip, ip)
+        }
+        Pattern::Ipv4Net => {
+            let net = ip.parse::<Ipv4Net>()?;
+            (
+                true,
+                net.network().to_bits().into(),
+                net.broadcast().to_bits().into(),
+            )
+        }
+        Pattern::Ipv4Range => {
+            let (lower, upper) = parse_ip_range::<Ipv4Addr>(ip)?;
+            (true, lower.to_bits().into(), upper.to_bits().into())
+        }
+        Pattern::Ipv6Addr => {
+            let ip = ip.parse::<Ipv6Addr>()?.to_bits();
+            (false, ip, ip)
+        }
+        Pattern::Ipv6Net => {
+            let net = ip.parse::<Ipv6Net>()?;
+            (false, net.network().to_bits(), net.broadcast().to_bits())
+        }
+        Pattern::Ipv6Range => {
+            let (lower, upper) = parse_ip_range::<Ipv6Addr>(ip)?;
+            (false, lower.to_bits(), upper.to_bits())
+        }
+    };
+
+    Ok(IpBounds {
+        is_ipv4,
+        lower,
+        upper,
+    })
+}
+
+// returns IPv4 and IPv6 entries in that order
+pub fn split_by_family(ip_list: Vec<String>) -> Result<(Vec<String>, Vec<String>)> {
+    let mut ipv4 = Vec::new();
+    let mut ipv6 = Vec::new();
+
+    for ip in ip_list {
+        if find_ip_match(&ip)?.is_ipv4() {
+            ipv4.push(ip);
+        } else {
+            ipv6.push(ip);
+        }
+    }
+
+    Ok((ipv4, ipv6))
+}
+
 pub fn create_list_ipv4(ip_list: Vec<String>) -> Result<Ipv4List> {
-    let mut ips = RoaringBitmap::new();
-    let mut subnets = Vec::new();
-    let mut ranges = Vec::new();
+    let mut bounds = Vec::with_capacity(ip_list.len());
 
     for ip in ip_list {
         let pat = find_ip_match(&ip)?;
 
         let ret: Result<(), String> = match pat {
             Pattern::Ipv4Addr => {
-                ips.insert(ip.parse::<Ipv4Addr>()?.to_bits());
+                let x = ip.parse::<Ipv4Addr>()?.to_bits();
+                bounds.push((x, x));
                 Ok(())
             }
             Pattern::Ipv4Net => {
-                subnets.push(ip.parse::<Ipv4Net>()?);
+                let x = ip.parse::<Ipv4Net>()?;
+                bounds.push((x.network().to_bits(), x.broadcast().to_bits()));
                 Ok(())
             }
             Pattern::Ipv4Range => {
-                let range: Vec<_> = ip.split('-').collect();
-
-                if let [lower, upper] = &range[0..2] {
-                    if lower >= upper {
-                        bail!(
-                            "'{}' IP on left side should be lower than the right one",
-                            ip
-                        );
-                    }
-                    ranges.push((lower.parse::<Ipv4Addr>()?, upper.parse::<Ipv4Addr>()?));
-                } else {
-                    bail!("'{}' wrong input for IPv4 range syntax", ip);
-                }
-
+                let (x, y) = parse_ip_range::<Ipv4Addr>(&ip)?;
+                bounds.push((x.to_bits(), y.to_bits()));
                 Ok(())
             }
             _ => Err("no matching pattern".to_owned()),
@@ -107,9 +333,42 @@ pub fn create_list_ipv4(ip_list: Vec<String>) -> Result<Ipv4List> {
     }
 
     Ok(Ipv4List {
-        ips,
-        subnets,
-        ranges,
+        intervals: Intervals::new(bounds),
+    })
+}
+
+pub fn create_list_ipv6(ip_list: Vec<String>) -> Result<Ipv6List> {
+    let mut bounds = Vec::with_capacity(ip_list.len());
+
+    for ip in ip_list {
+        let pat = find_ip_match(&ip)?;
+
+        let ret: Result<(), String> = match pat {
+            Pattern::Ipv6Addr => {
+                let x = ip.parse::<Ipv6Addr>()?.to_bits();
+                bounds.push((x, x));
+                Ok(())
+            }
+            Pattern::Ipv6Net => {
+                let x = ip.parse::<Ipv6Net>()?;
+                bounds.push((x.network().to_bits(), x.broadcast().to_bits()));
+                Ok(())
+            }
+            Pattern::Ipv6Range => {
+                let (x, y) = parse_ip_range::<Ipv6Addr>(&ip)?;
+                bounds.push((x.to_bits(), y.to_bits()));
+                Ok(())
+            }
+            _ => Err("no matching pattern".to_owned()),
+        };
+
+        if let Err(e) = ret {
+            bail!(e);
+        }
+    }
+
+    Ok(Ipv6List {
+        intervals: Intervals::new(bounds),
     })
 }
 
@@ -236,10 +495,8 @@ mod tests {
         let ret = create_list_ipv4(vec!["127.0.0.255".to_owned()]);
 
         let list = ret?;
-        assert_eq!(list.ips.len(), 1);
-        assert_eq!(list.subnets.len(), 0);
-        assert_eq!(list.ranges.len(), 0);
-        assert!(list.ips.contains(Ipv4Addr::new(127, 0, 0, 255).to_bits()));
+        let ip = Ipv4Addr::new(127, 0, 0, 255).to_bits();
+        assert_eq!(list.intervals.bounds, vec![(ip, ip)]);
 
         Ok(())
     }
@@ -249,11 +506,8 @@ mod tests {
         let ret = create_list_ipv4(vec!["127.0.0.255".to_owned(), "0.0.0.0".to_owned()]);
 
         let list = ret?;
-        assert_eq!(list.ips.len(), 2);
-        assert_eq!(list.subnets.len(), 0);
-        assert_eq!(list.ranges.len(), 0);
-        assert!(list.ips.contains(Ipv4Addr::new(127, 0, 0, 255).to_bits()));
-        assert!(list.ips.contains(Ipv4Addr::new(0, 0, 0, 0).to_bits()));
+        let ip = Ipv4Addr::new(127, 0, 0, 255).to_bits();
+        assert_eq!(list.intervals.bounds, vec![(0, 0), (ip, ip)]);
 
         Ok(())
     }
@@ -273,10 +527,8 @@ mod tests {
         let ret = create_list_ipv4(vec!["127.0.0.255/32".to_owned()]);
 
         let list = ret?;
-        assert_eq!(list.ips.len(), 0);
-        assert_eq!(list.subnets.len(), 1);
-        assert_eq!(list.ranges.len(), 0);
-        assert_eq!(list.subnets[0], "127.0.0.255/32".parse().unwrap());
+        let ip = Ipv4Addr::new(127, 0, 0, 255).to_bits();
+        assert_eq!(list.intervals.bounds, vec![(ip, ip)]);
 
         Ok(())
     }
@@ -286,11 +538,8 @@ mod tests {
         let ret = create_list_ipv4(vec!["127.0.0.255/32".to_owned(), "0.0.0.0/32".to_owned()]);
 
         let list = ret?;
-        assert_eq!(list.ips.len(), 0);
-        assert_eq!(list.subnets.len(), 2);
-        assert_eq!(list.ranges.len(), 0);
-        assert_eq!(list.subnets[0], "127.0.0.255/32".parse().unwrap());
-        assert_eq!(list.subnets[1], "0.0.0.0/32".parse().unwrap());
+        let ip = Ipv4Addr::new(127, 0, 0, 255).to_bits();
+        assert_eq!(list.intervals.bounds, vec![(0, 0), (ip, ip)]);
 
         Ok(())
     }
@@ -310,11 +559,9 @@ mod tests {
         let ret = create_list_ipv4(vec!["127.0.0.254-127.0.0.255".to_owned()]);
 
         let list = ret?;
-        assert_eq!(list.ips.len(), 0);
-        assert_eq!(list.subnets.len(), 0);
-        assert_eq!(list.ranges.len(), 1);
-        assert_eq!(list.ranges[0].0, Ipv4Addr::new(127, 0, 0, 254));
-        assert_eq!(list.ranges[0].1, Ipv4Addr::new(127, 0, 0, 255));
+        let lower = Ipv4Addr::new(127, 0, 0, 254).to_bits();
+        let upper = Ipv4Addr::new(127, 0, 0, 255).to_bits();
+        assert_eq!(list.intervals.bounds, vec![(lower, upper)]);
 
         Ok(())
     }
@@ -340,4 +587,321 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_find_ip_match_tp_ipv6addr() -> Result<()> {
+        for ip in ["::1", "2001:db8::1", "fe80::1:2:3:4", "::ffff:10.0.0.1"] {
+            assert_eq!(find_ip_match(ip)?, Pattern::Ipv6Addr);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_ip_match_tp_ipv6net() -> Result<()> {
+        for i in 0..=128 {
+            let ret = find_ip_match(format!("2001:db8::/{}", i).as_str());
+
+            assert_eq!(ret?, Pattern::Ipv6Net);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_ip_match_tn_ipv6net_invalid_subnet() -> Result<()> {
+        let ret = find_ip_match("2001:db8::/129").expect_err("must fail");
+
+        assert!(error::is_underlying::<String>(&ret));
+        assert!(ret.to_string().contains("no matching pattern"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_ip_match_tp_ipv6range() -> Result<()> {
+        let ret = find_ip_match("2001:db8::1-2001:db8::ff");
+
+        assert_eq!(ret?, Pattern::Ipv6Range);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_ip_match_tn_ipv6addr_invalid_character() -> Result<()> {
+        let ret = find_ip_match("2001:db8::g").expect_err("must fail");
+
+        assert!(error::is_underlying::<String>(&ret));
+        assert!(ret.to_string().contains("no matching pattern"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_family_tp_mixed() -> Result<()> {
+        let (ipv4, ipv6) = split_by_family(vec![
+            "10.0.0.1".to_owned(),
+            "2001:db8::/32".to_owned(),
+            "10.0.0.0/8".to_owned(),
+            "::1-::2".to_owned(),
+        ])?;
+
+        assert_eq!(ipv4, vec!["10.0.0.1", "10.0.0.0/8"]);
+        assert_eq!(ipv6, vec!["2001:db8::/32", "::1-::2"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_list_ipv4_tn_ipv6addr() -> Result<()> {
+        let ret = create_list_ipv4(vec!["::1".to_owned()]).expect_err("must fail");
+
+        assert!(error::is_underlying::<String>(&ret));
+        assert!(ret.to_string().contains("no matching pattern"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_list_ipv6_tp_mixed() -> Result<()> {
+        let ret = create_list_ipv6(vec![
+            "2001:db8::1".to_owned(),
+            "2001:db8:1::/48".to_owned(),
+            "2001:db8:2::1-2001:db8:2::ff".to_owned(),
+        ]);
+
+        let list = ret?;
+        assert_eq!(list.intervals.bounds.len(), 3);
+        assert!(list.contains(&"2001:db8::1".parse()?));
+        assert!(list.contains(&"2001:db8:1:ffff::1".parse()?));
+        assert!(list.contains(&"2001:db8:2::80".parse()?));
+        assert!(!list.contains(&"2001:db8::2".parse()?));
+        assert!(!list.contains(&"2001:db8:2::100".parse()?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_list_ipv6_tn_ipv6addr_double_compression() -> Result<()> {
+        let ret = create_list_ipv6(vec!["2001:db8::1::1".to_owned()]).expect_err("must fail");
+
+        assert!(error::is_underlying::<AddrParseError>(&ret));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_list_ipv6_tn_ipv6range_invalid() -> Result<()> {
+        let ret =
+            create_list_ipv6(vec!["2001:db8::ff-2001:db8::1".to_owned()]).expect_err("must fail");
+
+        assert!(error::is_underlying::<String>(&ret));
+        assert!(ret.to_string().contains("IP on left side"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_list_ipv6_tn_ipv4addr() -> Result<()> {
+        let ret = create_list_ipv6(vec!["10.0.0.1".to_owned()]).expect_err("must fail");
+
+        assert!(error::is_underlying::<String>(&ret));
+        assert!(ret.to_string().contains("no matching pattern"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ip_bounds_tp_ipv4() -> Result<()> {
+        let subnet = ip_bounds("10.0.0.1/8")?;
+        let range = ip_bounds("10.1.0.0-10.1.255.255")?;
+
+        assert_eq!(subnet.lower, 0x0a00_0000);
+        assert_eq!(subnet.upper, 0x0aff_ffff);
+        assert!(subnet.contains(&range));
+        assert!(!range.contains(&subnet));
+        assert_eq!(range.to_subnet().as_deref(), Some("10.1.0.0/16"));
+        assert_eq!(
+            ip_bounds("10.0.0.1")?.to_subnet().as_deref(),
+            Some("10.0.0.1/32")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ip_bounds_tp_ipv6_all() -> Result<()> {
+        let all = ip_bounds("::/0")?;
+
+        assert_eq!(all.span(), u128::MAX);
+        assert_eq!(all.to_subnet().as_deref(), Some("::/0"));
+        assert!(!all.contains(&ip_bounds("0.0.0.1")?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ip_bounds_tn_range_not_subnet() -> Result<()> {
+        assert_eq!(ip_bounds("10.0.0.1-10.0.0.2")?.to_subnet(), None);
+        assert_eq!(ip_bounds("10.0.0.0-10.0.0.2")?.to_subnet(), None);
+        assert!(ip_bounds("10.0.0.0-10.0.0.2")?.overlaps(&ip_bounds("10.0.0.2/31")?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_intervals_tp_merge() -> Result<()> {
+        let intervals = Intervals::new(vec![
+            (20u32, 30),
+            (0, 5),
+            (6, 9),
+            (25, 26),
+            (28, 40),
+            (u32::MAX - 1, u32::MAX),
+        ]);
+
+        assert_eq!(
+            intervals.bounds,
+            vec![(0, 9), (20, 40), (u32::MAX - 1, u32::MAX)]
+        );
+        assert!(intervals.contains(0));
+        assert!(intervals.contains(9));
+        assert!(!intervals.contains(10));
+        assert!(!intervals.contains(19));
+        assert!(intervals.contains(40));
+        assert!(!intervals.contains(41));
+        assert!(intervals.contains(u32::MAX));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_intervals_tp_covering() -> Result<()> {
+        let intervals = Intervals::new(vec![(0u32, 5), (6, 9), (20, 30)]);
+
+        assert_eq!(intervals.covering(2, 8), Some((0, 9)));
+        assert_eq!(intervals.covering(20, 20), Some((20, 30)));
+        assert_eq!(intervals.covering(8, 20), None);
+        assert_eq!(intervals.covering(25, 31), None);
+        assert_eq!(intervals.covering(10, 10), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_list_ipv4_tp_merged_entries() -> Result<()> {
+        let list = create_list_ipv4(
+            [
+                "10.0.0.0/24",
+                "10.0.1.0-10.0.1.255",
+                "10.0.0.7",
+                "10.0.2.0",
+                "192.0.2.0/24",
+            ]
+            .map(str::to_owned)
+            .into(),
+        )?;
+
+        assert_eq!(list.intervals.bounds.len(), 2);
+        assert!(list.contains(&"10.0.2.0".parse()?));
+        assert!(!list.contains(&"10.0.2.1".parse()?));
+        assert!(list.contains(&"192.0.2.255".parse()?));
+        assert!(!list.contains(&"192.0.3.0".parse()?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_list_ipv6_tp_all() -> Result<()> {
+        let list = create_list_ipv6(["::/0".to_owned(), "2001:db8::1".to_owned()].into())?;
+
+        assert_eq!(list.intervals.bounds.len(), 1);
+        assert!(list.contains(&"ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff".parse()?));
+
+        Ok(())
+    }
+
+    // deterministic pseudo-random numbers for the generated lists
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u32 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 32) as u32
+        }
+    }
+
+    fn generated_list(len: usize, seed: u64) -> Vec<String> {
+        let mut rng = Lcg(seed);
+
+        (0..len)
+            .map(|i| {
+                let ip = Ipv4Addr::from_bits(rng.next());
+
+                match i % 3 {
+                    0 => ip.to_string(),
+                    1 => Ipv4Net::new(ip, 16 + (rng.next() % 17) as u8)
+                        .unwrap()
+                        .trunc()
+                        .to_string(),
+                    _ => {
+                        let upper =
+                            Ipv4Addr::from_bits(ip.to_bits().saturating_add(1 + rng.next() % 4096));
+                        format!("{}-{}", ip, upper)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_create_list_ipv4_tp_same_as_linear_scan() -> Result<()> {
+        let entries = generated_list(3000, 7);
+        let list = create_list_ipv4(entries.clone())?;
+        let bounds: Vec<_> = entries.iter().map(|x| ip_bounds(x).unwrap()).collect();
+        let mut rng = Lcg(11);
+
+        for _ in 0..20000 {
+            let ip = rng.next();
+            let linear = bounds
+                .iter()
+                .any(|x| x.lower <= ip as u128 && ip as u128 <= x.upper);
+
+            assert_eq!(list.contains(&Ipv4Addr::from_bits(ip)), linear);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_intervals_tp_lookup_cost() -> Result<()> {
+        for (size, seed) in [(1_000, 1), (10_000, 2), (100_000, 3)] {
+            let list = create_list_ipv4(generated_list(size, seed))?;
+            let intervals = &list.intervals;
+
+            // few entries merge, so the list keeps about its size
+            assert!(intervals.bounds.len() > size / 2);
+
+            // a lookup compares about log2 of the intervals, a linear scan
+            // would compare each of them
+            let limit = intervals.bounds.len().ilog2() + 2;
+            let mut rng = Lcg(seed + 10);
+
+            for _ in 0..1000 {
+                let mut comparisons = 0;
+
+                intervals.contains_by(rng.next(), |lower, ip| {
+                    comparisons += 1;
+                    lower <= ip
+                });
+
+                assert!(comparisons <= limit);
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -1,28 +1,314 @@
 use std::ffi::{c_char, c_int};
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, bail};
-use clap::{Parser, error::ErrorKind};
+use clap::{Parser, ValueEnum, error::ErrorKind};
 
 use crate::c_utils;
 use crate::config;
+use crate::config_file;
+use crate::ffi::pam;
+use crate::rule::Action;
+
+/// Which list wins when a value is present in both the allow and deny list.
+///
+/// Deny lists are always evaluated before allow lists. With `deny` (default),
+/// a deny entry rejects the value regardless of the allow list. With `allow`,
+/// an explicit allow entry overrides a matching deny entry.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precedence {
+    Allow,
+    #[default]
+    Deny,
+}
+
+/// What to do when the value a filter needs is not available, e.g. the
+/// remote host has no entry in the neighbour table.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MissingPolicy {
+    Allow,
+    #[default]
+    Deny,
+}
+
+/// How many addresses of a resolved hostname must be in `--ip-allow` with
+/// `--resolve-rhost`. One address in `--ip-deny` is always enough to deny.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResolveMatch {
+    Any,
+    #[default]
+    All,
+}
+
+/// `audit` runs every check as in `enforce`, but only logs what would be
+/// denied and always returns the code given by `--audit-return`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Enforce,
+    Audit,
+}
+
+/// Return code of the audit mode, which leaves the decision to the other
+/// modules in the stack.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PassThrough {
+    #[default]
+    Ignore,
+    Success,
+}
 
+impl PassThrough {
+    pub fn to_pam(self) -> c_int {
+        match self {
+            PassThrough::Ignore => pam::PAM_IGNORE,
+            PassThrough::Success => pam::PAM_SUCCESS,
+        }
+    }
+}
+
+/// Layout of the decision logged once per phase. `kv` and `json` carry the
+/// fields decision, reason, matched, user, ruser, rhost, service, tty and
+/// phase; the messages of the checks become a single msg field.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Kv,
+    Json,
+}
+
+/// PAM return code chosen for an outcome, e.g. `--on-deny=perm-denied`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReturnCode {
+    Success,
+    Ignore,
+    AuthErr,
+    PermDenied,
+    #[default]
+    AuthinfoUnavail,
+    UserUnknown,
+}
+
+impl ReturnCode {
+    pub fn to_pam(self) -> c_int {
+        match self {
+            ReturnCode::Success => pam::PAM_SUCCESS,
+            ReturnCode::Ignore => pam::PAM_IGNORE,
+            ReturnCode::AuthErr => pam::PAM_AUTH_ERR,
+            ReturnCode::PermDenied => pam::PAM_PERM_DENIED,
+            ReturnCode::AuthinfoUnavail => pam::PAM_AUTHINFO_UNAVAIL,
+            ReturnCode::UserUnknown => pam::PAM_USER_UNKNOWN,
+        }
+    }
+}
+
+// options given more than once override each other, so that module arguments
+// take precedence over the config file
 #[derive(Parser, Debug)]
-#[command(version, about, arg_required_else_help(true))]
+#[command(version, about, arg_required_else_help(true), args_override_self(true))]
 pub struct Cli {
+    /// TOML file holding the same options as the module arguments, e.g.
+    /// /etc/security/network_filter.conf
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
     #[clap(long, value_delimiter(','))]
     pub ip_allow: Vec<String>,
 
+    #[clap(long, value_delimiter(','))]
+    pub ip_deny: Vec<String>,
+
+    /// Files with one entry per line in the format of the list option;
+    /// `#` starts a comment
+    #[clap(long)]
+    pub ip_allow_file: Vec<PathBuf>,
+
+    #[clap(long)]
+    pub ip_deny_file: Vec<PathBuf>,
+
     #[clap(long, value_delimiter(','))]
     pub mac_allow: Vec<String>,
 
+    /// File in /proc/net/arp layout used instead of the kernel neighbour table
+    #[clap(long)]
+    pub neigh_table: Option<PathBuf>,
+
+    #[clap(long, value_enum, default_value_t)]
+    pub mac_missing: MissingPolicy,
+
     #[clap(long, value_delimiter(','))]
     pub port_allow: Vec<String>,
 
+    /// MaxMind database in GeoLite2-Country format
+    #[clap(long)]
+    pub country_db: Option<PathBuf>,
+
+    /// ISO 3166-1 alpha-2 codes such as DE or US
+    #[clap(long, value_delimiter(','))]
+    pub country_allow: Vec<String>,
+
+    #[clap(long, value_delimiter(','))]
+    pub country_deny: Vec<String>,
+
+    /// MaxMind database in GeoLite2-ASN format
+    #[clap(long)]
+    pub asn_db: Option<PathBuf>,
+
+    /// Autonomous system numbers such as AS64496 or 64496
+    #[clap(long, value_delimiter(','))]
+    pub asn_allow: Vec<String>,
+
+    #[clap(long, value_delimiter(','))]
+    pub asn_deny: Vec<String>,
+
+    /// What to do when country or ASN allow lists are set but the host has
+    /// no entry in the database
+    #[clap(long, value_enum, default_value_t)]
+    pub geoip_missing: MissingPolicy,
+
     #[clap(long, value_delimiter(','))]
     pub user_allow: Vec<String>,
 
+    #[clap(long, value_delimiter(','))]
+    pub user_deny: Vec<String>,
+
+    #[clap(long)]
+    pub user_allow_file: Vec<PathBuf>,
+
+    #[clap(long)]
+    pub user_deny_file: Vec<PathBuf>,
+
+    /// Requesting users (PAM_RUSER) of su, sudo and similar services
+    #[clap(long, value_delimiter(','))]
+    pub ruser_allow: Vec<String>,
+
+    #[clap(long, value_delimiter(','))]
+    pub ruser_deny: Vec<String>,
+
+    /// What to do when ruser lists are set but PAM_RUSER is not
+    #[clap(long, value_enum, default_value_t)]
+    pub ruser_missing: MissingPolicy,
+
+    /// PAM services, e.g. sshd or sudo, the module admits when included from
+    /// a shared stack
+    #[clap(long, value_delimiter(','))]
+    pub service_allow: Vec<String>,
+
+    #[clap(long, value_delimiter(','))]
+    pub service_deny: Vec<String>,
+
+    /// Groups, primary or supplementary, whose members are allowed
+    #[clap(long, value_delimiter(','))]
+    pub group_allow: Vec<String>,
+
+    #[clap(long, value_delimiter(','))]
+    pub group_deny: Vec<String>,
+
+    /// Return code when the groups of the user cannot be looked up
+    #[clap(long, value_enum, default_value_t)]
+    pub group_error: ReturnCode,
+
     #[clap(long, value_delimiter(','))]
     pub domain_allow: Vec<String>,
+
+    #[clap(long, value_delimiter(','))]
+    pub domain_deny: Vec<String>,
+
+    #[clap(long)]
+    pub domain_allow_file: Vec<PathBuf>,
+
+    #[clap(long)]
+    pub domain_deny_file: Vec<PathBuf>,
+
+    /// Match IP hosts against domain rules using forward-confirmed reverse DNS
+    #[clap(long)]
+    pub fcrdns: bool,
+
+    /// Match hostnames against the IP lists by the addresses they resolve to
+    #[clap(long)]
+    pub resolve_rhost: bool,
+
+    #[clap(long, value_enum, default_value_t)]
+    pub resolve_match: ResolveMatch,
+
+    #[clap(long, value_enum, default_value_t)]
+    pub precedence: Precedence,
+
+    #[clap(long, value_enum, default_value_t)]
+    pub mode: Mode,
+
+    #[clap(long, value_enum, default_value_t)]
+    pub audit_return: PassThrough,
+
+    #[clap(long, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+
+    /// Glob patterns for PAM_TTY such as "tty[1-6]", "ttyS*" or ":0"
+    #[clap(long, value_delimiter(','))]
+    pub tty_allow: Vec<String>,
+
+    /// What to do when tty patterns are set but the session has no terminal
+    #[clap(long, value_enum, default_value_t)]
+    pub tty_missing: MissingPolicy,
+
+    /// Weekly window such as "Mon-Fri 08:00-19:00 Europe/Berlin"; logins
+    /// are allowed if any window contains the current time
+    #[clap(long)]
+    pub time_allow: Vec<String>,
+
+    /// Ordered rule such as "permit users=root,@admins hosts=10.1.0.0/24
+    /// services=sshd"; the first matching rule decides
+    #[clap(long)]
+    pub rule: Vec<String>,
+
+    /// Action taken when rules are set but none of them matches
+    #[clap(long, value_enum, default_value_t)]
+    pub default_action: Action,
+
+    #[clap(long, value_enum, default_value_t = ReturnCode::Success)]
+    pub on_allow: ReturnCode,
+
+    /// Return code of a denial; auth-err in the auth and perm-denied in the
+    /// account phase if not given
+    #[clap(long, value_enum)]
+    pub on_deny: Option<ReturnCode>,
+
+    /// Return code when rules are set but none of them matches; replaces
+    /// --default-action
+    #[clap(long, value_enum)]
+    pub on_no_match: Option<ReturnCode>,
+
+    /// Return code when the options or the config file are invalid
+    #[clap(long, value_enum, default_value_t)]
+    pub on_config_error: ReturnCode,
+
+    /// Return code when the PAM items cannot be read or a GeoIP, neighbour
+    /// table or `--resolve-rhost` lookup fails. A failed `--fcrdns` lookup is
+    /// not an error; the host only has no verified name then.
+    #[clap(long, value_enum, default_value_t)]
+    pub on_resolver_error: ReturnCode,
+}
+
+impl Cli {
+    /// Files the policy is read from besides the configuration file.
+    pub fn files(&self) -> Vec<&Path> {
+        let lists = [
+            &self.ip_allow_file,
+            &self.ip_deny_file,
+            &self.user_allow_file,
+            &self.user_deny_file,
+            &self.domain_allow_file,
+            &self.domain_deny_file,
+        ];
+
+        self.country_db
+            .iter()
+            .chain(&self.asn_db)
+            .chain(lists.into_iter().flatten())
+            .map(PathBuf::as_path)
+            .collect()
+    }
 }
 
 fn parse_c_args(argc: c_int, argv: *const *const c_char) -> Vec<String> {
@@ -44,7 +330,44 @@ fn parse_c_args(argc: c_int, argv: *const *const c_char) -> Vec<String> {
     vec
 }
 
-pub fn process_pam_args(argc: c_int, argv: *const *const c_char) -> Result<Cli> {
+// values of a long option as written in `args`, without parsing the others
+fn raw_values<'a>(args: &'a [String], option: &str) -> Vec<&'a str> {
+    let mut values = Vec::new();
+
+    for (i, arg) in args.iter().enumerate() {
+        let value = match arg.strip_prefix(option) {
+            Some("") => args.get(i + 1).map(String::as_str),
+            Some(x) => x.strip_prefix('='),
+            None => None,
+        };
+
+        values.extend(value);
+    }
+
+    values
+}
+
+/// Looks up `--on-config-error` in the module arguments without parsing the
+/// others, so that it also applies when they or the config file are invalid.
+/// The last valid occurrence wins, as in the parser.
+pub fn config_error_code(argc: c_int, argv: *const *const c_char) -> ReturnCode {
+    let args = parse_c_args(argc, argv);
+
+    raw_values(&args, "--on-config-error")
+        .into_iter()
+        .filter_map(|x| ReturnCode::from_str(x, false).ok())
+        .next_back()
+        .unwrap_or_default()
+}
+
+/// `--config` as given in the module arguments; the config file itself
+/// cannot name another one.
+pub fn config_path(args: &[String]) -> Option<&str> {
+    raw_values(args, "--config").pop()
+}
+
+/// Module arguments as strings, preceded by the module name.
+pub fn pam_args(argc: c_int, argv: *const *const c_char) -> Result<Vec<String>> {
     if argc == 0 {
         bail!(clap::Error::raw(
             ErrorKind::MissingRequiredArgument,
@@ -52,10 +375,30 @@ pub fn process_pam_args(argc: c_int, argv: *const *const c_char) -> Result<Cli>
         ));
     }
 
-    let args = parse_c_args(argc, argv);
-    let cli = Cli::try_parse_from(args)?;
+    Ok(parse_c_args(argc, argv))
+}
 
-    Ok(cli)
+#[cfg(test)]
+pub fn process_pam_args(argc: c_int, argv: *const *const c_char) -> Result<Cli> {
+    process_args(pam_args(argc, argv)?)
+}
+
+/// Parses module arguments given as strings, `args[0]` being the program
+/// name, and merges the config file if `--config` is given.
+pub fn process_args(args: Vec<String>) -> Result<Cli> {
+    let cli = Cli::try_parse_from(&args)?;
+
+    let Some(path) = &cli.config else {
+        return Ok(cli);
+    };
+
+    // list options accumulate, so the config file is placed first and the
+    // module arguments are appended after it
+    let mut merged = vec![args[0].clone()];
+    merged.extend(config_file::args_from_config_file(path)?);
+    merged.extend(args.into_iter().skip(1));
+
+    Ok(Cli::try_parse_from(merged)?)
 }
 
 #[cfg(test)]
@@ -226,4 +569,452 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_process_pam_args_tp_deny_lists() -> Result<()> {
+        let argv = [
+            c"--ip-allow=10.0.0.0/8".as_ptr(),
+            c"--ip-deny=10.0.66.0/24".as_ptr(),
+            c"--user-deny".as_ptr(),
+            c"guest,nobody".as_ptr(),
+            c"--domain-deny=evil.example.com".as_ptr(),
+        ];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr());
+        let cli = ret?;
+
+        assert_eq!(cli.ip_allow, vec!["10.0.0.0/8"]);
+        assert_eq!(cli.ip_deny, vec!["10.0.66.0/24"]);
+        assert_eq!(cli.user_deny, vec!["guest", "nobody"]);
+        assert_eq!(cli.domain_deny, vec!["evil.example.com"]);
+        assert_eq!(cli.precedence, Precedence::Deny);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_precedence_allow() -> Result<()> {
+        let argv = [c"--precedence=allow".as_ptr()];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr());
+        let cli = ret?;
+
+        assert_eq!(cli.precedence, Precedence::Allow);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tn_precedence_invalid() -> Result<()> {
+        let argv = [c"--precedence=both".as_ptr()];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr()).expect_err("must fail");
+
+        assert!(error::is_underlying::<clap::Error>(&ret));
+        assert_eq!(
+            error::downcast_ref::<clap::Error>(&ret)?.kind(),
+            ErrorKind::InvalidValue
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_mac_options() -> Result<()> {
+        let argv = [
+            c"--mac-allow=aa:bb:cc:dd:ee:ff".as_ptr(),
+            c"--neigh-table=/tmp/arp".as_ptr(),
+            c"--mac-missing=allow".as_ptr(),
+        ];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr());
+        let cli = ret?;
+
+        assert_eq!(cli.mac_allow, vec!["aa:bb:cc:dd:ee:ff"]);
+        assert_eq!(cli.neigh_table, Some(PathBuf::from("/tmp/arp")));
+        assert_eq!(cli.mac_missing, MissingPolicy::Allow);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_fcrdns() -> Result<()> {
+        let argv = [
+            c"--domain-allow=host.example.com".as_ptr(),
+            c"--fcrdns".as_ptr(),
+        ];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr());
+        let cli = ret?;
+
+        assert!(cli.fcrdns);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_resolve_rhost() -> Result<()> {
+        let argv = [
+            c"--ip-allow=10.0.0.0/8".as_ptr(),
+            c"--resolve-rhost".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert!(cli.resolve_rhost);
+        assert_eq!(cli.resolve_match, ResolveMatch::All);
+
+        let argv = [c"--resolve-match=any".as_ptr()];
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert!(!cli.resolve_rhost);
+        assert_eq!(cli.resolve_match, ResolveMatch::Any);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_config_merged() -> Result<()> {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/config/network_filter.conf");
+        let config = std::ffi::CString::new(format!("--config={}", path.display()))?;
+        let argv = [
+            config.as_ptr(),
+            c"--ip-allow=192.168.0.0/16".as_ptr(),
+            c"--precedence=deny".as_ptr(),
+        ];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr());
+        let cli = ret?;
+
+        assert_eq!(
+            cli.ip_allow,
+            vec!["10.0.0.0/8", "2001:db8::/32", "192.168.0.0/16"]
+        );
+        assert_eq!(cli.ip_deny, vec!["10.0.66.0/24"]);
+        assert_eq!(cli.user_deny, vec!["guest"]);
+        // module arguments override the config file
+        assert_eq!(cli.precedence, Precedence::Deny);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tn_config_not_found() -> Result<()> {
+        let argv = [c"--config=/nonexistent.conf".as_ptr()];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr()).expect_err("must fail");
+
+        assert!(ret.to_string().contains("cannot read config file"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_rules_not_split_on_comma() -> Result<()> {
+        let argv = [
+            c"--rule=permit users=root,@admins hosts=10.1.0.0/24".as_ptr(),
+            c"--rule".as_ptr(),
+            c"deny users=root".as_ptr(),
+            c"--default-action=permit".as_ptr(),
+        ];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr());
+        let cli = ret?;
+
+        assert_eq!(
+            cli.rule,
+            vec![
+                "permit users=root,@admins hosts=10.1.0.0/24",
+                "deny users=root"
+            ]
+        );
+        assert_eq!(cli.default_action, Action::Permit);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_groups() -> Result<()> {
+        let argv = [
+            c"--group-allow=wheel,admins".as_ptr(),
+            c"--group-deny".as_ptr(),
+            c"guests".as_ptr(),
+            c"--group-error=perm-denied".as_ptr(),
+        ];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr());
+        let cli = ret?;
+
+        assert_eq!(cli.group_allow, vec!["wheel", "admins"]);
+        assert_eq!(cli.group_deny, vec!["guests"]);
+        assert_eq!(cli.group_error, ReturnCode::PermDenied);
+        assert_eq!(cli.group_error.to_pam(), pam::PAM_PERM_DENIED);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_group_error_default() -> Result<()> {
+        let argv = [c"--group-allow=wheel".as_ptr()];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.group_error.to_pam(), pam::PAM_AUTHINFO_UNAVAIL);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_time_allow_not_split_on_comma() -> Result<()> {
+        let argv = [
+            c"--time-allow=Sat,Sun 10:00-14:00 UTC".as_ptr(),
+            c"--time-allow=Mon-Fri 08:00-19:00".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(
+            cli.time_allow,
+            vec!["Sat,Sun 10:00-14:00 UTC", "Mon-Fri 08:00-19:00"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_services() -> Result<()> {
+        let argv = [
+            c"--service-allow=sshd,cockpit".as_ptr(),
+            c"--service-deny=sudo".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.service_allow, vec!["sshd", "cockpit"]);
+        assert_eq!(cli.service_deny, vec!["sudo"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_ruser() -> Result<()> {
+        let argv = [
+            c"--ruser-allow=doe,joe".as_ptr(),
+            c"--ruser-deny=guest".as_ptr(),
+            c"--ruser-missing=allow".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.ruser_allow, vec!["doe", "joe"]);
+        assert_eq!(cli.ruser_deny, vec!["guest"]);
+        assert_eq!(cli.ruser_missing, MissingPolicy::Allow);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_tty() -> Result<()> {
+        let argv = [
+            c"--tty-allow=tty[1-6],ttyS0".as_ptr(),
+            c"--tty-missing=allow".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.tty_allow, vec!["tty[1-6]", "ttyS0"]);
+        assert_eq!(cli.tty_missing, MissingPolicy::Allow);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_geoip() -> Result<()> {
+        let argv = [
+            c"--country-db=/var/lib/GeoIP/GeoLite2-Country.mmdb".as_ptr(),
+            c"--country-deny=KP,IR".as_ptr(),
+            c"--asn-db=/var/lib/GeoIP/GeoLite2-ASN.mmdb".as_ptr(),
+            c"--asn-allow=AS64496".as_ptr(),
+            c"--geoip-missing=allow".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(
+            cli.country_db,
+            Some(PathBuf::from("/var/lib/GeoIP/GeoLite2-Country.mmdb"))
+        );
+        assert_eq!(cli.country_deny, vec!["KP", "IR"]);
+        assert_eq!(cli.asn_allow, vec!["AS64496"]);
+        assert_eq!(cli.geoip_missing, MissingPolicy::Allow);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_audit_mode() -> Result<()> {
+        let argv = [
+            c"--mode=audit".as_ptr(),
+            c"--audit-return=success".as_ptr(),
+            c"--ip-allow=10.0.0.0/8".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.mode, Mode::Audit);
+        assert_eq!(cli.audit_return.to_pam(), pam::PAM_SUCCESS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_audit_return_default() -> Result<()> {
+        let argv = [c"--mode=audit".as_ptr()];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.audit_return.to_pam(), pam::PAM_IGNORE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_return_codes() -> Result<()> {
+        let argv = [
+            c"--on-allow=ignore".as_ptr(),
+            c"--on-deny=perm-denied".as_ptr(),
+            c"--on-no-match=ignore".as_ptr(),
+            c"--on-resolver-error=auth-err".as_ptr(),
+        ];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.on_allow, ReturnCode::Ignore);
+        assert_eq!(cli.on_deny, Some(ReturnCode::PermDenied));
+        assert_eq!(cli.on_no_match, Some(ReturnCode::Ignore));
+        assert_eq!(cli.on_config_error, ReturnCode::AuthinfoUnavail);
+        assert_eq!(cli.on_resolver_error, ReturnCode::AuthErr);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_return_codes_default() -> Result<()> {
+        let argv = [c"--ip-allow=10.0.0.0/8".as_ptr()];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.on_allow.to_pam(), pam::PAM_SUCCESS);
+        assert_eq!(cli.on_deny, None);
+        assert_eq!(cli.on_no_match, None);
+        assert_eq!(cli.on_config_error.to_pam(), pam::PAM_AUTHINFO_UNAVAIL);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_error_code_tp_with_invalid_args() -> Result<()> {
+        let argv = [
+            c"--ip-allow".as_ptr(),
+            c"--on-config-error".as_ptr(),
+            c"ignore".as_ptr(),
+            c"--no-such-option".as_ptr(),
+        ];
+
+        assert!(process_pam_args(argv.len() as c_int, argv.as_ptr()).is_err());
+        assert_eq!(
+            config_error_code(argv.len() as c_int, argv.as_ptr()),
+            ReturnCode::Ignore
+        );
+
+        let argv = [
+            c"--on-config-error=success".as_ptr(),
+            c"--on-config-error=perm-denied".as_ptr(),
+        ];
+
+        assert_eq!(
+            config_error_code(argv.len() as c_int, argv.as_ptr()),
+            ReturnCode::PermDenied
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_error_code_tn_invalid_value() -> Result<()> {
+        let argv = [c"--on-config-error=maybe".as_ptr()];
+
+        assert_eq!(
+            config_error_code(argv.len() as c_int, argv.as_ptr()),
+            ReturnCode::AuthinfoUnavail
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tp_log_format() -> Result<()> {
+        let argv = [c"--log-format=json".as_ptr()];
+
+        let cli = process_pam_args(argv.len() as c_int, argv.as_ptr())?;
+
+        assert_eq!(cli.log_format, LogFormat::Json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_pam_args_tn_log_format() -> Result<()> {
+        let argv = [c"--log-format=xml".as_ptr()];
+
+        let ret = process_pam_args(argv.len() as c_int, argv.as_ptr()).expect_err("must fail");
+
+        assert_eq!(
+            error::downcast_ref::<clap::Error>(&ret)?.kind(),
+            ErrorKind::InvalidValue
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_path_tp() -> Result<()> {
+        let args: Vec<String> = [
+            "lib",
+            "--ip-allow=::1",
+            "--config",
+            "/a.conf",
+            "--config=/b.conf",
+        ]
+        .map(str::to_owned)
+        .into();
+
+        assert_eq!(config_path(&args), Some("/b.conf"));
+        assert_eq!(config_path(&args[..2]), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_tp_lists_and_databases() -> Result<()> {
+        let args: Vec<String> = [
+            "lib",
+            "--ip-allow-file=/a.list",
+            "--ip-allow-file=/b.list",
+            "--domain-deny-file=/c.list",
+            "--asn-db=/asn.mmdb",
+        ]
+        .map(str::to_owned)
+        .into();
+
+        let cli = process_args(args)?;
+
+        assert_eq!(
+            cli.files(),
+            ["/asn.mmdb", "/a.list", "/b.list", "/c.list"].map(Path::new)
+        );
+
+        Ok(())
+    }
 }
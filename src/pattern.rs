@@ -1,28 +1,106 @@
+use std::sync::LazyLock;
+
 use fancy_regex::Regex;
 
+// compiles a pattern on first use and keeps it for the life of the process,
+// which spans many authentications in daemons such as cockpit-ws
+macro_rules! static_regex {
+    ($pat: expr) => {{
+        static REGEX: LazyLock<Regex> = LazyLock::new(|| {
+            let pat: &str = &$pat;
+            Regex::new(pat).unwrap()
+        });
+        &REGEX
+    }};
+}
+
 const PAT_IPV4_STR: &str = r"\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}";
+// hex groups separated by at least one colon, optionally ending with an
+// embedded IPv4 address such as ::ffff:10.0.0.1
+const PAT_IPV6_STR: &str = r"[0-9a-fA-F]{0,4}(:[0-9a-fA-F]{0,4}){1,7}(:\d{1,3}(\.\d{1,3}){3})?";
 
-pub fn pat_ipv4() -> Regex {
-    Regex::new(format!(r"^{}$", PAT_IPV4_STR).as_str()).unwrap()
+pub fn pat_ipv4() -> &'static Regex {
+    static_regex!(format!(r"^{}$", PAT_IPV4_STR))
 }
 
-pub fn pat_ipv4_range() -> Regex {
-    Regex::new(format!(r"^{p}-{p}$", p = PAT_IPV4_STR).as_str()).unwrap()
+pub fn pat_ipv4_range() -> &'static Regex {
+    static_regex!(format!(r"^{p}-{p}$", p = PAT_IPV4_STR))
 }
 
-pub fn pat_ipv4_subnet() -> Regex {
+pub fn pat_ipv4_subnet() -> &'static Regex {
     // IPv4 followed by CIDR notation /0-32
     // \d       => 0-9
     // [1-2]\d  => 10-29
     // 3[0-2]   => 30-32
-    Regex::new(format!(r"^{}/(\d|[1-2]\d|3[0-2])$", PAT_IPV4_STR).as_str()).unwrap()
+    static_regex!(format!(r"^{}/(\d|[1-2]\d|3[0-2])$", PAT_IPV4_STR))
+}
+
+pub fn pat_ipv6() -> &'static Regex {
+    static_regex!(format!(r"^{}$", PAT_IPV6_STR))
+}
+
+pub fn pat_ipv6_range() -> &'static Regex {
+    static_regex!(format!(r"^{p}-{p}$", p = PAT_IPV6_STR))
+}
+
+pub fn pat_ipv6_subnet() -> &'static Regex {
+    // IPv6 followed by CIDR notation /0-128
+    // \d{1,2}   => 0-99
+    // 1[0-1]\d  => 100-119
+    // 12[0-8]   => 120-128
+    static_regex!(format!(r"^{}/(\d{{1,2}}|1[0-1]\d|12[0-8])$", PAT_IPV6_STR))
+}
+
+pub fn pat_port() -> &'static Regex {
+    static_regex!(r"^\d{1,5}$")
+}
+
+pub fn pat_port_range() -> &'static Regex {
+    static_regex!(r"^\d{1,5}-\d{1,5}$")
+}
+
+pub fn pat_mac() -> &'static Regex {
+    static_regex!(r"^([0-9a-fA-F]{2}:){5}[0-9a-fA-F]{2}$")
+}
+
+// from RegExr FQDN: https://regexr.com/3g5j0
+const PAT_FQDN_STR: &str = r"(?!:\/\/)(?=.{1,255}$)((.{1,63}\.){1,127}(?![0-9]*$)[a-z0-9-]+\.?)";
+
+pub fn pat_fqdn() -> &'static Regex {
+    static_regex!(format!(r"^{}$", PAT_FQDN_STR))
+}
+
+pub fn pat_fqdn_wildcard() -> &'static Regex {
+    // FQDN optionally preceded by "*." (subdomains only) or "." (domain and
+    // subdomains); the wildcard is not allowed anywhere else
+    static_regex!(format!(r"^(\*\.|\.)?(?!.*\*){}$", PAT_FQDN_STR))
+}
+
+pub fn pat_time_range() -> &'static Regex {
+    // HH:MM-HH:MM with 24:00 allowed as the end of the day
+    static_regex!(r"^([01]\d|2[0-3]):[0-5]\d-(([01]\d|2[0-3]):[0-5]\d|24:00)$")
+}
+
+pub fn pat_country() -> &'static Regex {
+    // ISO 3166-1 alpha-2
+    static_regex!(r"^[A-Za-z]{2}$")
+}
+
+pub fn pat_asn() -> &'static Regex {
+    static_regex!(r"^([Aa][Ss])?\d{1,10}$")
+}
+
+pub fn pat_service() -> &'static Regex {
+    // file names in /etc/pam.d
+    static_regex!(r"^[A-Za-z0-9_][A-Za-z0-9_.+-]*$")
 }
 
-pub fn pat_fqdn() -> Regex {
-    // from RegExr FQDN: https://regexr.com/3g5j0
-    Regex::new(r"^(?!:\/\/)(?=.{1,255}$)((.{1,63}\.){1,127}(?![0-9]*$)[a-z0-9-]+\.?)$").unwrap()
+pub fn pat_username() -> &'static Regex {
+    static_regex!(r"^[a-z_]([a-z0-9_-]{0,31}|[a-z0-9_-]{0,30}\$)$")
 }
 
-pub fn pat_username() -> Regex {
-    Regex::new(r"^[a-z_]([a-z0-9_-]{0,31}|[a-z0-9_-]{0,30}\$)$").unwrap()
+pub fn pat_groupname() -> &'static Regex {
+    // looser than usernames since directory groups often contain capitals,
+    // dots and spaces, e.g. "Domain Users"
+    static_regex!(r"^[A-Za-z0-9_][A-Za-z0-9_. -]{0,255}$")
 }
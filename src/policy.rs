@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use jiff::tz;
+
+use crate::filter;
+use crate::geoip::{self, GeoDb};
+use crate::list_file;
+use crate::parser::{
+    Cli, LogFormat, MissingPolicy, Mode, PassThrough, Precedence, ResolveMatch, ReturnCode,
+};
+use crate::rule::{self, Action, Rule};
+
+use filter::{
+    Filter, FilterAsn, FilterCountry, FilterDomain, FilterGroup, FilterIp, FilterMac, FilterPort,
+    FilterService, FilterTime, FilterTty, FilterUser,
+};
+
+#[derive(Debug)]
+pub struct Policy {
+    pub service_allow: FilterService,
+    pub service_deny: FilterService,
+    pub user_allow: FilterUser,
+    pub user_deny: FilterUser,
+    pub ruser_allow: FilterUser,
+    pub ruser_deny: FilterUser,
+    pub ruser_missing: MissingPolicy,
+    pub group_allow: FilterGroup,
+    pub group_deny: FilterGroup,
+    pub group_error: ReturnCode,
+    pub ip_allow: FilterIp,
+    pub ip_deny: FilterIp,
+    pub domain_allow: FilterDomain,
+    pub domain_deny: FilterDomain,
+    pub port_allow: FilterPort,
+    pub country_db: Option<GeoDb>,
+    pub country_allow: FilterCountry,
+    pub country_deny: FilterCountry,
+    pub asn_db: Option<GeoDb>,
+    pub asn_allow: FilterAsn,
+    pub asn_deny: FilterAsn,
+    pub geoip_missing: MissingPolicy,
+    pub mac_allow: FilterMac,
+    pub tty_allow: FilterTty,
+    pub tty_missing: MissingPolicy,
+    pub time_allow: FilterTime,
+    pub neigh_table: Option<PathBuf>,
+    pub mac_missing: MissingPolicy,
+    pub precedence: Precedence,
+    pub mode: Mode,
+    pub audit_return: PassThrough,
+    pub log_format: LogFormat,
+    pub on_allow: ReturnCode,
+    pub on_deny: Option<ReturnCode>,
+    pub on_no_match: Option<ReturnCode>,
+    pub on_resolver_error: ReturnCode,
+    pub fcrdns: bool,
+    pub resolve_rhost: bool,
+    pub resolve_match: ResolveMatch,
+    pub rules: Vec<Rule>,
+    pub default_action: Action,
+}
+
+// a database is only opened when one of its lists is set
+fn open_geo_db(path: Option<&Path>, is_used: bool, kind: &str) -> Result<Option<GeoDb>> {
+    if !is_used {
+        return Ok(None);
+    }
+
+    match path {
+        Some(x) => Ok(Some(geoip::open_db(x)?)),
+        None => bail!("'--{}-db' is required by the {} lists", kind, kind),
+    }
+}
+
+/// Validates one value of a list option with the same constructor as
+/// `policy_from_cli`, so that an invalid entry can be located, e.g. in the
+/// config file. Other options are left to the parser.
+pub fn check_entry(option: &str, value: &str) -> Result<()> {
+    let values = vec![value.to_owned()];
+
+    match option {
+        "ip-allow" | "ip-deny" => filter::filter_from_ips(values).map(drop),
+        "user-allow" | "user-deny" | "ruser-allow" | "ruser-deny" => {
+            filter::filter_from_users(values).map(drop)
+        }
+        "group-allow" | "group-deny" => filter::filter_from_groups(values).map(drop),
+        "service-allow" | "service-deny" => filter::filter_from_services(values).map(drop),
+        "domain-allow" | "domain-deny" => filter::filter_from_domains(values).map(drop),
+        "port-allow" => filter::filter_from_ports(values).map(drop),
+        "country-allow" | "country-deny" => filter::filter_from_countries(values).map(drop),
+        "asn-allow" | "asn-deny" => filter::filter_from_asns(values).map(drop),
+        "mac-allow" => filter::filter_from_macs(values).map(drop),
+        "tty-allow" => filter::filter_from_ttys(values).map(drop),
+        "time-allow" => filter::filter_from_times(values, tz::db()).map(drop),
+        "rule" => rule::parse_rule(value).map(drop),
+        _ => Ok(()),
+    }
+}
+
+pub fn policy_from_cli(cli: Cli) -> Result<Policy> {
+    let country_allow = filter::filter_from_countries(cli.country_allow)?;
+    let country_deny = filter::filter_from_countries(cli.country_deny)?;
+    let asn_allow = filter::filter_from_asns(cli.asn_allow)?;
+    let asn_deny = filter::filter_from_asns(cli.asn_deny)?;
+
+    let is_country_used = !country_allow.is_empty() || !country_deny.is_empty();
+    let is_asn_used = !asn_allow.is_empty() || !asn_deny.is_empty();
+
+    Ok(Policy {
+        service_allow: filter::filter_from_services(cli.service_allow)?,
+        service_deny: filter::filter_from_services(cli.service_deny)?,
+        user_allow: list_file::build_with_files(
+            cli.user_allow,
+            &cli.user_allow_file,
+            filter::filter_from_users,
+        )?,
+        user_deny: list_file::build_with_files(
+            cli.user_deny,
+            &cli.user_deny_file,
+            filter::filter_from_users,
+        )?,
+        ruser_allow: filter::filter_from_users(cli.ruser_allow)?,
+        ruser_deny: filter::filter_from_users(cli.ruser_deny)?,
+        ruser_missing: cli.ruser_missing,
+        group_allow: filter::filter_from_groups(cli.group_allow)?,
+        group_deny: filter::filter_from_groups(cli.group_deny)?,
+        group_error: cli.group_error,
+        ip_allow: list_file::build_with_files(
+            cli.ip_allow,
+            &cli.ip_allow_file,
+            filter::filter_from_ips,
+        )?,
+        ip_deny: list_file::build_with_files(
+            cli.ip_deny,
+            &cli.ip_deny_file,
+            filter::filter_from_ips,
+        )?,
+        domain_allow: list_file::build_with_files(
+            cli.domain_allow,
+            &cli.domain_allow_file,
+            filter::filter_from_domains,
+        )?,
+        domain_deny: list_file::build_with_files(
+            cli.domain_deny,
+            &cli.domain_deny_file,
+            filter::filter_from_domains,
+        )?,
+        port_allow: filter::filter_from_ports(cli.port_allow)?,
+        country_db: open_geo_db(cli.country_db.as_deref(), is_country_used, "country")?,
+        country_allow,
+        country_deny,
+        asn_db: open_geo_db(cli.asn_db.as_deref(), is_asn_used, "asn")?,
+        asn_allow,
+        asn_deny,
+        geoip_missing: cli.geoip_missing,
+        mac_allow: filter::filter_from_macs(cli.mac_allow)?,
+        tty_allow: filter::filter_from_ttys(cli.tty_allow)?,
+        tty_missing: cli.tty_missing,
+        time_allow: filter::filter_from_times(cli.time_allow, tz::db())?,
+        neigh_table: cli.neigh_table,
+        mac_missing: cli.mac_missing,
+        precedence: cli.precedence,
+        mode: cli.mode,
+        audit_return: cli.audit_return,
+        log_format: cli.log_format,
+        on_allow: cli.on_allow,
+        on_deny: cli.on_deny,
+        on_no_match: cli.on_no_match,
+        on_resolver_error: cli.on_resolver_error,
+        fcrdns: cli.fcrdns,
+        resolve_rhost: cli.resolve_rhost,
+        resolve_match: cli.resolve_match,
+        rules: rule::parse_rules(cli.rule)?,
+        default_action: cli.default_action,
+    })
+}
@@ -0,0 +1,428 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+
+use crate::filter::{
+    self, Filter, FilterDomain, FilterGroup, FilterIp, FilterService, FilterTty, FilterUser,
+};
+use crate::item::RemoteHost;
+use crate::pattern;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Action {
+    Permit,
+    #[default]
+    Deny,
+}
+
+#[derive(Debug, Default)]
+pub struct UserSelector {
+    users: FilterUser,
+    groups: FilterGroup,
+}
+
+#[derive(Debug)]
+pub struct HostSelector {
+    ips: FilterIp,
+    domains: FilterDomain,
+    // `LOCAL` matches logins without a remote host
+    local: bool,
+}
+
+// a missing selector matches anything, like `ALL`
+#[derive(Debug)]
+pub struct Rule {
+    pub action: Action,
+    users: Option<UserSelector>,
+    // never matches a login without PAM_RUSER
+    rusers: Option<UserSelector>,
+    hosts: Option<HostSelector>,
+    services: Option<FilterService>,
+    // never matches a session without a tty
+    ttys: Option<FilterTty>,
+}
+
+/// Everything a rule can be matched against.
+#[derive(Debug)]
+pub struct RuleContext<'a> {
+    pub user: &'a str,
+    pub groups: &'a [String],
+    // empty if PAM_RUSER is not set
+    pub ruser: &'a str,
+    pub ruser_groups: &'a [String],
+    pub rhost: &'a RemoteHost,
+    // forward-confirmed name of an IP rhost, if any
+    pub domain: Option<&'a str>,
+    pub service: &'a str,
+    // empty for sessions without a terminal
+    pub tty: &'a str,
+}
+
+impl UserSelector {
+    fn matches(&self, user: &str, groups: &[String]) -> bool {
+        self.users.contains(user) || self.groups.contains_any(groups)
+    }
+}
+
+impl HostSelector {
+    fn matches(&self, rhost: &RemoteHost, domain: Option<&str>) -> bool {
+        match rhost {
+            RemoteHost::Local => self.local,
+            RemoteHost::Ip(ip) => {
+                self.ips.contains(ip) || domain.is_some_and(|x| self.domains.contains(x))
+            }
+            RemoteHost::Name(name) => self.domains.contains(name),
+        }
+    }
+}
+
+impl Rule {
+    pub fn needs_groups(&self) -> bool {
+        self.users.as_ref().is_some_and(|x| !x.groups.is_empty())
+    }
+
+    pub fn needs_ruser_groups(&self) -> bool {
+        self.rusers.as_ref().is_some_and(|x| !x.groups.is_empty())
+    }
+
+    pub fn needs_domain(&self) -> bool {
+        self.hosts.as_ref().is_some_and(|x| !x.domains.is_empty())
+    }
+
+    pub fn matches(&self, ctx: &RuleContext) -> bool {
+        self.users
+            .as_ref()
+            .is_none_or(|x| x.matches(ctx.user, ctx.groups))
+            && self
+                .rusers
+                .as_ref()
+                .is_none_or(|x| !ctx.ruser.is_empty() && x.matches(ctx.ruser, ctx.ruser_groups))
+            && self
+                .hosts
+                .as_ref()
+                .is_none_or(|x| x.matches(ctx.rhost, ctx.domain))
+            && self
+                .services
+                .as_ref()
+                .is_none_or(|x| x.contains(ctx.service))
+            && self
+                .ttys
+                .as_ref()
+                .is_none_or(|x| !ctx.tty.is_empty() && x.contains(ctx.tty))
+    }
+}
+
+fn parse_users(values: Vec<String>) -> Result<UserSelector> {
+    let mut users = Vec::new();
+    let mut groups = Vec::new();
+
+    for value in values {
+        match value.strip_prefix('@') {
+            Some(group) => groups.push(group.to_owned()),
+            None => users.push(value),
+        }
+    }
+
+    Ok(UserSelector {
+        users: filter::filter_from_users(users)?,
+        groups: filter::filter_from_groups(groups)?,
+    })
+}
+
+fn parse_hosts(values: Vec<String>) -> Result<HostSelector> {
+    let pat_fqdn = pattern::pat_fqdn_wildcard();
+    let mut ips = Vec::new();
+    let mut domains = Vec::new();
+    let mut local = false;
+
+    // IPs, CIDRs and ranges never look like a domain name, so anything that
+    // is not a domain is handed to the IP parser for validation
+    for value in values {
+        if value == "LOCAL" {
+            local = true;
+        } else if pat_fqdn.is_match(&value.to_ascii_lowercase())? {
+            domains.push(value);
+        } else {
+            ips.push(value);
+        }
+    }
+
+    Ok(HostSelector {
+        ips: filter::filter_from_ips(ips)?,
+        domains: filter::filter_from_domains(domains)?,
+        local,
+    })
+}
+
+/// Parses a rule of the form
+/// `<permit|deny> [users=<list>] [rusers=<list>] [hosts=<list>]
+/// [services=<list>] [ttys=<list>]`, where each list is comma-separated.
+/// `rusers` selects the requesting user of su and sudo, and `ttys` takes glob
+/// patterns. Users prefixed with `@` select a group, `LOCAL` in hosts selects
+/// logins without a remote host, and `ALL` or an omitted field matches
+/// anything.
+pub fn parse_rule(rule: &str) -> Result<Rule> {
+    let mut fields = rule.split_whitespace();
+
+    let action = match fields.next() {
+        Some("permit") => Action::Permit,
+        Some("deny") => Action::Deny,
+        _ => bail!("'{}' rule must start with permit or deny", rule),
+    };
+
+    let mut parsed = Rule {
+        action,
+        users: None,
+        rusers: None,
+        hosts: None,
+        services: None,
+        ttys: None,
+    };
+    let mut seen = HashSet::new();
+
+    for field in fields {
+        let Some((key, value)) = field.split_once('=') else {
+            bail!("'{}' rule field must be key=value", field);
+        };
+
+        if !seen.insert(key) {
+            bail!("'{}' rule field given more than once", key);
+        }
+
+        let values: Vec<String> = value
+            .split(',')
+            .filter(|x| !x.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        if values.is_empty() {
+            bail!("'{}' rule field has no value", field);
+        }
+
+        let is_all = values.iter().any(|x| x == "ALL");
+
+        match key {
+            "users" if is_all => {}
+            "users" => parsed.users = Some(parse_users(values)?),
+            "rusers" if is_all => {}
+            "rusers" => parsed.rusers = Some(parse_users(values)?),
+            "hosts" if is_all => {}
+            "hosts" => parsed.hosts = Some(parse_hosts(values)?),
+            "services" if is_all => {}
+            "services" => parsed.services = Some(filter::filter_from_services(values)?),
+            "ttys" if is_all => {}
+            "ttys" => parsed.ttys = Some(filter::filter_from_ttys(values)?),
+            _ => bail!("'{}' unknown rule field", key),
+        }
+    }
+
+    Ok(parsed)
+}
+
+pub fn parse_rules(rules: Vec<String>) -> Result<Vec<Rule>> {
+    rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| parse_rule(rule).with_context(|| format!("rule {}", i + 1)))
+        .collect()
+}
+
+/// Returns the 1-based index and the rule that matches first.
+pub fn find_first_match<'a>(rules: &'a [Rule], ctx: &RuleContext) -> Option<(usize, &'a Rule)> {
+    rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule.matches(ctx))
+        .map(|(i, rule)| (i + 1, rule))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(rhost: &str) -> RemoteHost {
+        RemoteHost::parse(rhost)
+    }
+
+    fn ctx<'a>(user: &'a str, groups: &'a [String], rhost: &'a RemoteHost) -> RuleContext<'a> {
+        RuleContext {
+            user,
+            groups,
+            ruser: "",
+            ruser_groups: &[],
+            rhost,
+            domain: None,
+            service: "sshd",
+            tty: "ssh",
+        }
+    }
+
+    #[test]
+    fn test_parse_rule_tp_all_fields() -> Result<()> {
+        let rule =
+            parse_rule("permit users=root,@wheel hosts=10.1.0.0/24,*.ci.example services=sshd")?;
+
+        assert_eq!(rule.action, Action::Permit);
+        assert!(rule.needs_groups());
+        assert!(rule.matches(&ctx("root", &[], &host("10.1.0.7"))));
+        assert!(rule.matches(&ctx(
+            "doe",
+            &["wheel".to_owned()],
+            &host("runner1.ci.example")
+        )));
+        assert!(!rule.matches(&ctx("doe", &[], &host("10.1.0.7"))));
+        assert!(!rule.matches(&ctx("root", &[], &host("10.2.0.7"))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tp_all_keyword() -> Result<()> {
+        let rule = parse_rule("deny users=ALL hosts=ALL")?;
+
+        assert_eq!(rule.action, Action::Deny);
+        assert!(!rule.needs_groups());
+        assert!(rule.matches(&ctx("anyone", &[], &host("192.0.2.1"))));
+        assert!(rule.matches(&ctx("anyone", &[], &RemoteHost::Local)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tp_local() -> Result<()> {
+        let rule = parse_rule("permit hosts=LOCAL")?;
+
+        assert!(rule.matches(&ctx("root", &[], &RemoteHost::Local)));
+        assert!(!rule.matches(&ctx("root", &[], &host("10.0.0.1"))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tp_services() -> Result<()> {
+        let rule = parse_rule("deny services=sudo,su")?;
+        let mut context = ctx("root", &[], &RemoteHost::Local);
+
+        assert!(!rule.matches(&context));
+        context.service = "sudo";
+        assert!(rule.matches(&context));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tp_rusers() -> Result<()> {
+        let rule = parse_rule("permit users=root rusers=@admins,doe services=sudo")?;
+        let admins = ["admins".to_owned()];
+        let mut context = ctx("root", &[], &RemoteHost::Local);
+        context.service = "sudo";
+
+        assert!(rule.needs_ruser_groups());
+        assert!(!rule.needs_groups());
+
+        // PAM_RUSER not set
+        assert!(!rule.matches(&context));
+
+        context.ruser = "doe";
+        assert!(rule.matches(&context));
+
+        context.ruser = "joe";
+        assert!(!rule.matches(&context));
+
+        context.ruser_groups = &admins;
+        assert!(rule.matches(&context));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tp_ttys() -> Result<()> {
+        let rule = parse_rule("permit users=root ttys=tty[1-6],ttyS0")?;
+        let mut context = ctx("root", &[], &RemoteHost::Local);
+
+        assert!(!rule.matches(&context));
+
+        context.tty = "/dev/tty2";
+        assert!(rule.matches(&context));
+
+        // session without a terminal
+        context.tty = "";
+        assert!(!rule.matches(&context));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tn_action() -> Result<()> {
+        let ret = parse_rule("allow users=root").expect_err("must fail");
+
+        assert!(ret.to_string().contains("must start with permit or deny"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tn_unknown_field() -> Result<()> {
+        let ret = parse_rule("permit user=root").expect_err("must fail");
+
+        assert!(ret.to_string().contains("unknown rule field"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tn_duplicate_field() -> Result<()> {
+        let ret = parse_rule("permit users=root users=doe").expect_err("must fail");
+
+        assert!(ret.to_string().contains("more than once"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rule_tn_invalid_host() -> Result<()> {
+        let ret = parse_rule("permit hosts=10.0.0.0/33").expect_err("must fail");
+
+        assert!(ret.to_string().contains("no matching pattern"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rules_tn_index_in_error() -> Result<()> {
+        let ret = parse_rules(vec![
+            "permit users=root".to_owned(),
+            "deny users=".to_owned(),
+        ])
+        .expect_err("must fail");
+
+        assert_eq!(ret.to_string(), "rule 2");
+        assert!(format!("{:#}", ret).contains("has no value"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_first_match_tp_order() -> Result<()> {
+        let rules = parse_rules(vec![
+            "permit users=root hosts=10.1.0.0/24".to_owned(),
+            "deny users=root".to_owned(),
+            "permit hosts=10.0.0.0/8".to_owned(),
+        ])?;
+
+        let ret = find_first_match(&rules, &ctx("root", &[], &host("10.1.0.1")));
+        assert_eq!(ret.map(|(i, x)| (i, x.action)), Some((1, Action::Permit)));
+
+        let ret = find_first_match(&rules, &ctx("root", &[], &host("10.2.0.1")));
+        assert_eq!(ret.map(|(i, x)| (i, x.action)), Some((2, Action::Deny)));
+
+        let ret = find_first_match(&rules, &ctx("doe", &[], &host("10.2.0.1")));
+        assert_eq!(ret.map(|(i, x)| (i, x.action)), Some((3, Action::Permit)));
+
+        let ret = find_first_match(&rules, &ctx("doe", &[], &host("192.0.2.1")));
+        assert!(ret.is_none());
+
+        Ok(())
+    }
+}
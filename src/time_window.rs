@@ -0,0 +1,245 @@
+use anyhow::{Context, Result, bail};
+use jiff::Timestamp;
+use jiff::tz::{TimeZone, TimeZoneDatabase};
+
+use crate::pattern;
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// Weekly access window such as `Mon-Fri 08:00-19:00 Europe/Berlin`.
+///
+/// A window whose end is not after its start crosses midnight, and the part
+/// after midnight belongs to the day it was opened on, so `Fri 22:00-02:00`
+/// includes Saturday 01:00 but not Friday 01:00.
+#[derive(Debug)]
+pub struct TimeWindow {
+    // bit n is set for the n-th day counted from Monday
+    days: u8,
+    // minutes since midnight; `end` is exclusive
+    start: u16,
+    end: u16,
+    tz: TimeZone,
+}
+
+impl TimeWindow {
+    fn has_day(&self, day: i8) -> bool {
+        self.days & (1 << day) != 0
+    }
+
+    pub fn contains(&self, now: Timestamp) -> bool {
+        let zoned = now.to_zoned(self.tz.clone());
+        let day = zoned.weekday().to_monday_zero_offset();
+        let minute = zoned.hour() as u16 * 60 + zoned.minute() as u16;
+
+        if self.start < self.end {
+            return self.has_day(day) && (self.start..self.end).contains(&minute);
+        }
+
+        let yesterday = (day + 6) % 7;
+
+        (self.has_day(day) && minute >= self.start)
+            || (self.has_day(yesterday) && minute < self.end)
+    }
+}
+
+fn parse_weekday(day: &str) -> Result<u8> {
+    match WEEKDAYS.iter().position(|x| x.eq_ignore_ascii_case(day)) {
+        Some(x) => Ok(x as u8),
+        None => bail!("'{}' wrong weekday syntax", day),
+    }
+}
+
+// "Mon-Fri", "Sat,Sun" or "Fri-Mon", which wraps around the week
+fn parse_days(days: &str) -> Result<u8> {
+    let mut mask = 0;
+
+    for part in days.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+            None => (parse_weekday(part)?, parse_weekday(part)?),
+        };
+
+        let mut day = first;
+
+        loop {
+            mask |= 1 << day;
+
+            if day == last {
+                break;
+            }
+
+            day = (day + 1) % 7;
+        }
+    }
+
+    Ok(mask)
+}
+
+fn parse_minutes(time: &str) -> u16 {
+    let (hour, minute) = time.split_once(':').unwrap();
+
+    hour.parse::<u16>().unwrap() * 60 + minute.parse::<u16>().unwrap()
+}
+
+/// Parses `<days> <HH:MM-HH:MM> [time zone]`. Time zone names are looked up
+/// in `tzdb`; without one the system time zone is used.
+pub fn parse_time_window(window: &str, tzdb: &TimeZoneDatabase) -> Result<TimeWindow> {
+    let fields: Vec<&str> = window.split_whitespace().collect();
+
+    let (days, times, tz) = match fields[..] {
+        [days, times] => (days, times, TimeZone::system()),
+        [days, times, name] => {
+            let tz = tzdb
+                .get(name)
+                .with_context(|| format!("'{}' unknown time zone", name))?;
+
+            (days, times, tz)
+        }
+        _ => bail!(
+            "'{}' time window must be '<days> <HH:MM-HH:MM> [time zone]'",
+            window
+        ),
+    };
+
+    if !pattern::pat_time_range().is_match(times)? {
+        bail!("'{}' wrong time range syntax", times);
+    }
+
+    let (start, end) = times.split_once('-').unwrap();
+    let start = parse_minutes(start);
+    let end = parse_minutes(end);
+
+    if start == end {
+        bail!("'{}' time range is empty", times);
+    }
+
+    Ok(TimeWindow {
+        days: parse_days(days)?,
+        start,
+        end,
+        tz,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture_tzdb() -> Result<TimeZoneDatabase> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/zoneinfo");
+
+        Ok(TimeZoneDatabase::from_dir(path)?)
+    }
+
+    fn at(timestamp: &str) -> Timestamp {
+        timestamp.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_time_window_tp_office_hours() -> Result<()> {
+        let window = parse_time_window("Mon-Fri 08:00-19:00 Europe/Berlin", &fixture_tzdb()?)?;
+
+        // Monday, UTC+2
+        assert!(window.contains(at("2026-10-19T06:00:00Z")));
+        assert!(window.contains(at("2026-10-19T16:59:00Z")));
+        assert!(!window.contains(at("2026-10-19T05:59:00Z")));
+        assert!(!window.contains(at("2026-10-19T17:00:00Z")));
+        // Saturday
+        assert!(!window.contains(at("2026-10-24T10:00:00Z")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tp_dst_change() -> Result<()> {
+        let window = parse_time_window("Mon-Fri 08:00-19:00 Europe/Berlin", &fixture_tzdb()?)?;
+
+        // Monday after the switch to UTC+1
+        assert!(window.contains(at("2026-10-26T07:00:00Z")));
+        assert!(!window.contains(at("2026-10-26T06:30:00Z")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tp_cross_midnight() -> Result<()> {
+        let window = parse_time_window("Fri 22:00-02:00 UTC", &fixture_tzdb()?)?;
+
+        assert!(window.contains(at("2026-10-23T22:00:00Z")));
+        assert!(window.contains(at("2026-10-24T01:59:00Z")));
+        assert!(!window.contains(at("2026-10-24T02:00:00Z")));
+        assert!(!window.contains(at("2026-10-23T01:00:00Z")));
+        assert!(!window.contains(at("2026-10-24T23:00:00Z")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tp_days_wrap_and_list() -> Result<()> {
+        let tzdb = fixture_tzdb()?;
+        let window = parse_time_window("Sat-Mon 00:00-24:00 UTC", &tzdb)?;
+
+        assert!(window.contains(at("2026-10-25T12:00:00Z")));
+        assert!(window.contains(at("2026-10-26T23:59:00Z")));
+        assert!(!window.contains(at("2026-10-27T00:00:00Z")));
+
+        let window = parse_time_window("tue,THU 09:00-10:00 UTC", &tzdb)?;
+
+        assert!(window.contains(at("2026-10-20T09:30:00Z")));
+        assert!(window.contains(at("2026-10-22T09:30:00Z")));
+        assert!(!window.contains(at("2026-10-21T09:30:00Z")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tn_time_syntax() -> Result<()> {
+        let ret =
+            parse_time_window("Mon-Fri 8:00-19:00 UTC", &fixture_tzdb()?).expect_err("must fail");
+
+        assert!(ret.to_string().contains("wrong time range syntax"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tn_weekday() -> Result<()> {
+        let ret =
+            parse_time_window("Mon-Fry 08:00-19:00 UTC", &fixture_tzdb()?).expect_err("must fail");
+
+        assert_eq!(ret.to_string(), "'Fry' wrong weekday syntax");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tn_empty_range() -> Result<()> {
+        let ret =
+            parse_time_window("Mon 10:00-10:00 UTC", &fixture_tzdb()?).expect_err("must fail");
+
+        assert!(ret.to_string().contains("time range is empty"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tn_unknown_time_zone() -> Result<()> {
+        let ret = parse_time_window("Mon 08:00-19:00 Mars/Olympus", &fixture_tzdb()?)
+            .expect_err("must fail");
+
+        assert_eq!(ret.to_string(), "'Mars/Olympus' unknown time zone");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time_window_tn_fields() -> Result<()> {
+        let ret = parse_time_window("Mon-Fri", &fixture_tzdb()?).expect_err("must fail");
+
+        assert!(ret.to_string().contains("time window must be"));
+
+        Ok(())
+    }
+}
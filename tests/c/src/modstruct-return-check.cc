@@ -8,7 +8,7 @@ namespace {
 
 TEST(PamListfileModstruct, pam_sm_acct_mgmt_return_check) {
   EXPECT_EQ(_pam_listfile_modstruct.pam_sm_acct_mgmt(nullptr, 0, 0, nullptr),
-            PAM_IGNORE);
+            PAM_AUTHINFO_UNAVAIL);
 }
 
 TEST(PamListfileModstruct, pam_sm_open_session_return_check) {
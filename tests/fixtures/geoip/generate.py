@@ -0,0 +1,175 @@
+#!/usr/bin/env python3
+"""Writes the small MaxMind DB fixtures used by the GeoIP tests.
+
+Only documentation ranges are used, so the databases do not describe any
+real network. Run from any directory:
+
+    python3 tests/fixtures/geoip/generate.py
+"""
+
+import ipaddress
+import os
+import struct
+
+RECORD_SIZE = 24
+METADATA_MARKER = b"\xab\xcd\xefMaxMind.com"
+# 2026-01-01T00:00:00Z, fixed so the output is reproducible
+BUILD_EPOCH = 1767225600
+
+COUNTRIES = {
+    "192.0.2.0/24": ("DE", "Germany"),
+    "198.51.100.0/25": ("US", "United States"),
+    "2001:db8::/32": ("FR", "France"),
+}
+
+ASNS = {
+    "192.0.2.0/24": (64496, "Example Transit"),
+    "198.51.100.0/24": (64500, "Example Hosting"),
+    "2001:db8:1::/48": (64511, "Example Research"),
+}
+
+
+class Uint16(int):
+    pass
+
+
+class Uint32(int):
+    pass
+
+
+class Uint64(int):
+    pass
+
+
+def encode_control(type_id, size):
+    if type_id <= 7:
+        first, extended = type_id << 5, b""
+    else:
+        first, extended = 0, bytes([type_id - 7])
+
+    if size < 29:
+        return bytes([first | size]) + extended
+    if size < 285:
+        return bytes([first | 29]) + extended + bytes([size - 29])
+    if size < 65821:
+        return bytes([first | 30]) + extended + struct.pack(">H", size - 285)
+
+    return bytes([first | 31]) + extended + struct.pack(">I", size - 65821)[1:]
+
+
+def encode_uint(type_id, value):
+    payload = value.to_bytes((value.bit_length() + 7) // 8, "big")
+
+    return encode_control(type_id, len(payload)) + payload
+
+
+def encode(value):
+    if isinstance(value, str):
+        payload = value.encode()
+        return encode_control(2, len(payload)) + payload
+    if isinstance(value, Uint16):
+        return encode_uint(5, value)
+    if isinstance(value, Uint32):
+        return encode_uint(6, value)
+    if isinstance(value, Uint64):
+        return encode_uint(9, value)
+    if isinstance(value, dict):
+        out = encode_control(7, len(value))
+        for key, item in value.items():
+            out += encode(key) + encode(item)
+        return out
+    if isinstance(value, list):
+        out = encode_control(11, len(value))
+        for item in value:
+            out += encode(item)
+        return out
+
+    raise TypeError(f"unsupported type {type(value)}")
+
+
+def network_bits(network):
+    network = ipaddress.ip_network(network)
+
+    value = int(network.network_address)
+    prefixlen = network.prefixlen
+
+    # IPv4 networks live in the IPv4-compatible range ::/96 of the IPv6 tree
+    if network.version == 4:
+        prefixlen += 96
+
+    return [(value >> (127 - i)) & 1 for i in range(prefixlen)]
+
+
+def write_database(path, database_type, entries):
+    # each node is [left, right]; a record is None, ("node", index) or
+    # ("data", offset)
+    nodes = [[None, None]]
+    data = b""
+
+    for network, record in entries.items():
+        offset = len(data)
+        data += encode(record)
+
+        bits = network_bits(network)
+        node = 0
+
+        for bit in bits[:-1]:
+            if nodes[node][bit] is None:
+                nodes.append([None, None])
+                nodes[node][bit] = ("node", len(nodes) - 1)
+            node = nodes[node][bit][1]
+
+        nodes[node][bits[-1]] = ("data", offset)
+
+    node_count = len(nodes)
+
+    def record_value(record):
+        if record is None:
+            return node_count
+        if record[0] == "node":
+            return record[1]
+        return node_count + 16 + record[1]
+
+    tree = b""
+
+    for left, right in nodes:
+        tree += record_value(left).to_bytes(3, "big")
+        tree += record_value(right).to_bytes(3, "big")
+
+    metadata = {
+        "binary_format_major_version": Uint16(2),
+        "binary_format_minor_version": Uint16(0),
+        "build_epoch": Uint64(BUILD_EPOCH),
+        "database_type": database_type,
+        "description": {"en": f"{database_type} test fixture"},
+        "ip_version": Uint16(6),
+        "languages": ["en"],
+        "node_count": Uint32(node_count),
+        "record_size": Uint16(RECORD_SIZE),
+    }
+
+    with open(path, "wb") as f:
+        f.write(tree + bytes(16) + data + METADATA_MARKER + encode(metadata))
+
+
+def main():
+    root = os.path.dirname(os.path.abspath(__file__))
+
+    countries = {
+        network: {"country": {"iso_code": code, "names": {"en": name}}}
+        for network, (code, name) in COUNTRIES.items()
+    }
+    asns = {
+        network: {
+            "autonomous_system_number": Uint32(number),
+            "autonomous_system_organization": organization,
+        }
+        for network, (number, organization) in ASNS.items()
+    }
+
+    write_database(os.path.join(root, "country.mmdb"), "GeoLite2-Country", countries)
+    write_database(os.path.join(root, "asn.mmdb"), "GeoLite2-ASN", asns)
+
+
+if __name__ == "__main__":
+    main()